@@ -1,8 +1,328 @@
-use std::error::Error;
+use std::{error::Error, io::Write, path::PathBuf};
 
-use tokio;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use ewubd_timetable_calendar_lib::{
+    auth,
+    calendar::{self, Reminders, TimetableOptions},
+    conflicts,
+    courses::{self, Course},
+    diff, exams, holidays, import, overrides, pdf,
+    portal::PortalConfig,
+    semester, utils,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Generate EWU class timetables from the command line")]
+struct Cli {
+    /// Portal session cookie as printed by `login`
+    #[arg(long, global = true, env = "EWU_SESSION")]
+    session: Option<String>,
+
+    /// Base URL of the portal to talk to
+    #[arg(
+        long,
+        global = true,
+        env = "EWU_PORTAL_URL",
+        default_value = ewubd_timetable_calendar_lib::portal::DEFAULT_BASE_URL
+    )]
+    portal_url: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Log in to the portal and print the session cookie
+    Login {
+        #[arg(short, long, env = "EWU_USERNAME")]
+        username: String,
+        #[arg(short, long, env = "EWU_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+    /// List all semesters
+    Semesters,
+    /// List the courses taken in a semester
+    Courses {
+        /// Semester ID as listed by `semesters`
+        #[arg(short, long, required_unless_present = "from_ics")]
+        semester: Option<u16>,
+        /// Read the courses from a previously exported calendar instead of the portal
+        #[arg(long, conflicts_with = "semester")]
+        from_ics: Option<PathBuf>,
+    },
+    /// Export the timetable of a semester as an iCalendar file
+    Export {
+        /// Semester ID as listed by `semesters`
+        #[arg(short, long, required_unless_present = "from_ics")]
+        semester: Option<u16>,
+        /// Read the courses from a previously exported calendar instead of the portal
+        #[arg(long, conflicts_with = "semester", requires_all = ["name", "start_date", "end_date"])]
+        from_ics: Option<PathBuf>,
+        /// Calendar name, defaults to the semester name
+        #[arg(long)]
+        name: Option<String>,
+        /// First day of classes, defaults to the semester start date
+        #[arg(long)]
+        start_date: Option<NaiveDate>,
+        /// Last day of classes, defaults to the semester end date
+        #[arg(long)]
+        end_date: Option<NaiveDate>,
+        /// File of holidays and closures to skip, one "YYYY-MM-DD, Name" per line
+        #[arg(long)]
+        holidays: Vec<PathBuf>,
+        /// Don't skip the fixed-date public holidays bundled with the tool
+        #[arg(long)]
+        no_bundled_holidays: bool,
+        /// File of one-off schedule changes, one "<course>, YYYY-MM-DD, <change>" per line
+        /// where the change is "cancel", "add <start>-<end> [@ <room>]" or
+        /// "move [<date>] [<start>-<end>] [@ <room>]"
+        #[arg(long)]
+        overrides: Vec<PathBuf>,
+        /// CSV or JSON file of midterm and final exams, one
+        /// "<course>,<midterm|final>,YYYY-MM-DD,<start>,<end>[,<room>]" per line in CSV.
+        /// Classes end before the first final exam.
+        #[arg(long)]
+        exams: Vec<PathBuf>,
+        /// Remind this many minutes before each class
+        #[arg(long, value_name = "MINUTES")]
+        remind: Option<u32>,
+        /// Remind this many minutes before each lab instead, defaults to --remind
+        #[arg(long, value_name = "MINUTES")]
+        remind_lab: Option<u32>,
+        /// File to write the calendar to, defaults to stdout. Events that are
+        /// unchanged since the file was last written keep their timestamps.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Render the weekly timetable of a semester as a printable PDF
+    Pdf {
+        /// Semester ID as listed by `semesters`
+        #[arg(short, long, required_unless_present = "from_ics")]
+        semester: Option<u16>,
+        /// Read the courses from a previously exported calendar instead of the portal
+        #[arg(long, conflicts_with = "semester")]
+        from_ics: Option<PathBuf>,
+        /// Title printed on the page, defaults to the semester or calendar name
+        #[arg(long)]
+        name: Option<String>,
+        /// File to write the PDF to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show what changed in a timetable since a calendar was exported
+    Diff {
+        /// Previously exported calendar
+        old: PathBuf,
+        /// Calendar to compare against, leave out to compare against the portal
+        #[arg(required_unless_present = "semester")]
+        new: Option<PathBuf>,
+        /// Semester ID to fetch the current courses of from the portal
+        #[arg(short, long, conflicts_with = "new")]
+        semester: Option<u16>,
+    },
+}
+
+fn authenticated_client(
+    portal: &PortalConfig,
+    session: Option<&str>,
+) -> Result<reqwest::Client, Box<dyn Error>> {
+    let session = session.ok_or("No session provided, run `login` and pass --session")?;
+    Ok(utils::build_authenticated_client(portal, session)?)
+}
+
+async fn find_semester(
+    client: &reqwest::Client,
+    portal: &PortalConfig,
+    semester_id: u16,
+) -> Result<semester::Semester, Box<dyn Error>> {
+    semester::get_all_semesters(client, portal)
+        .await?
+        .into_iter()
+        .find(|s| s.id == semester_id)
+        .ok_or_else(|| format!("Semester {} not found", semester_id).into())
+}
+
+/// Prints scheduling conflicts between the courses to stderr
+fn warn_conflicts(courses: &[Course]) {
+    for conflict in conflicts::find_conflicts(courses) {
+        eprintln!("warning: {}", conflict);
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let session = cli.session.as_deref();
+    let portal = PortalConfig::new(&cli.portal_url);
+
+    match cli.command {
+        Command::Login { username, password } => {
+            let session_id = auth::login(&portal, &username, &password).await?;
+            println!("{}", session_id);
+        }
+        Command::Semesters => {
+            let client = authenticated_client(&portal, session)?;
+            for semester in semester::get_all_semesters(&client, &portal).await? {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    semester.id, semester.name, semester.start_date, semester.end_date
+                );
+            }
+        }
+        Command::Courses { semester, from_ics } => {
+            let courses = match (from_ics, semester) {
+                (Some(path), _) => import::parse_timetable(&std::fs::read_to_string(path)?)?,
+                (None, Some(semester)) => {
+                    let client = authenticated_client(&portal, session)?;
+                    courses::get_courses(&client, &portal, semester).await?
+                }
+                (None, None) => return Err("Pass --semester or --from-ics".into()),
+            };
+            warn_conflicts(&courses);
+            for course in courses {
+                println!(
+                    "{} ({}) - {}",
+                    course.course_code, course.section, course.lecturer
+                );
+                for period in &course.periods {
+                    println!(
+                        "  {} {}–{} @ {}",
+                        period.day, period.start_time, period.end_time, period.room
+                    );
+                }
+            }
+        }
+        Command::Export {
+            semester,
+            from_ics,
+            name,
+            start_date,
+            end_date,
+            holidays: holiday_files,
+            no_bundled_holidays,
+            overrides: override_files,
+            exams: exam_files,
+            remind,
+            remind_lab,
+            output,
+        } => {
+            let mut options = TimetableOptions {
+                reminders: Reminders {
+                    class: remind,
+                    lab: remind_lab,
+                },
+                ..Default::default()
+            };
+            if !no_bundled_holidays {
+                options.holidays = holidays::bundled_holidays();
+            }
+            for path in holiday_files {
+                let text = std::fs::read_to_string(&path)?;
+                options.holidays.extend(holidays::parse_holidays(&text)?);
+            }
+            for path in override_files {
+                let text = std::fs::read_to_string(&path)?;
+                options.overrides.extend(overrides::parse_overrides(&text)?);
+            }
+            for path in exam_files {
+                let text = std::fs::read_to_string(&path)?;
+                options.exams.extend(exams::parse_exams(&text)?);
+            }
+
+            let (courses, name, start_date, end_date) = match (from_ics, semester) {
+                (Some(path), _) => {
+                    let courses = import::parse_timetable(&std::fs::read_to_string(path)?)?;
+                    (
+                        courses,
+                        name.ok_or("--name is required with --from-ics")?,
+                        start_date.ok_or("--start-date is required with --from-ics")?,
+                        end_date.ok_or("--end-date is required with --from-ics")?,
+                    )
+                }
+                (None, Some(semester)) => {
+                    let client = authenticated_client(&portal, session)?;
+                    let semester = find_semester(&client, &portal, semester).await?;
+                    let courses = courses::get_courses(&client, &portal, semester.id).await?;
+                    options.semester_id = Some(semester.id);
+                    (
+                        courses,
+                        name.unwrap_or(semester.name),
+                        start_date.unwrap_or(semester.start_date),
+                        end_date.unwrap_or(semester.end_date),
+                    )
+                }
+                (None, None) => return Err("Pass --semester or --from-ics".into()),
+            };
+
+            warn_conflicts(&courses);
+            let ical = calendar::build_timetable(courses, &name, start_date, end_date, &options)?;
+
+            match output {
+                Some(path) => {
+                    let ical = match std::fs::read_to_string(&path) {
+                        Ok(previous) => calendar::reuse_stamps(&ical, &previous),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => ical,
+                        Err(e) => return Err(e.into()),
+                    };
+                    std::fs::write(path, ical)?
+                }
+                None => std::io::stdout().write_all(ical.as_bytes())?,
+            }
+        }
+        Command::Pdf {
+            semester,
+            from_ics,
+            name,
+            output,
+        } => {
+            let (courses, name) = match (from_ics, semester) {
+                (Some(path), _) => {
+                    let ical = std::fs::read_to_string(path)?;
+                    let name = match name {
+                        Some(name) => name,
+                        None => import::parse_calendar_name(&ical)?
+                            .unwrap_or_else(|| "Timetable".to_string()),
+                    };
+                    (import::parse_timetable(&ical)?, name)
+                }
+                (None, Some(semester)) => {
+                    let client = authenticated_client(&portal, session)?;
+                    let semester = find_semester(&client, &portal, semester).await?;
+                    let courses = courses::get_courses(&client, &portal, semester.id).await?;
+                    (courses, name.unwrap_or(semester.name))
+                }
+                (None, None) => return Err("Pass --semester or --from-ics".into()),
+            };
+
+            warn_conflicts(&courses);
+            let pdf = pdf::build_timetable_pdf(&courses, &name);
+            match output {
+                Some(path) => std::fs::write(path, pdf)?,
+                None => std::io::stdout().write_all(&pdf)?,
+            }
+        }
+        Command::Diff { old, new, semester } => {
+            let old = import::parse_timetable(&std::fs::read_to_string(old)?)?;
+            let new = match (new, semester) {
+                (Some(path), _) => import::parse_timetable(&std::fs::read_to_string(path)?)?,
+                (None, Some(semester)) => {
+                    let client = authenticated_client(&portal, session)?;
+                    courses::get_courses(&client, &portal, semester).await?
+                }
+                (None, None) => return Err("Pass a calendar or --semester to compare".into()),
+            };
+
+            let changes = diff::diff(&old, &new);
+            if changes.is_empty() {
+                println!("No changes");
+            }
+            for change in changes {
+                println!("{}", change);
+            }
+        }
+    }
+
     Ok(())
 }
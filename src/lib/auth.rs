@@ -1,13 +1,16 @@
 use scraper::{Html, Selector};
-use std::error::Error;
 
-use crate::utils::USER_AGENT;
+use crate::{
+    error::{Result, TimetableError},
+    portal::PortalConfig,
+    utils::USER_AGENT,
+};
 use reqwest::{
     header::{self},
     Response,
 };
 
-pub async fn fetch_login_page() -> Result<Response, Box<dyn Error>> {
+pub async fn fetch_login_page(config: &PortalConfig) -> Result<Response> {
     let client = reqwest::Client::builder()
         .use_rustls_tls()
         .default_headers({
@@ -20,89 +23,173 @@ pub async fn fetch_login_page() -> Result<Response, Box<dyn Error>> {
         })
         .build()?;
 
-    let login_page_res = client.get("https://portal.ewubd.edu/").send().await?;
+    let login_page_res = client.get(config.url("/")).send().await?;
     Ok(login_page_res)
 }
 
-pub fn get_session_id(login_page_res: &Response) -> Result<String, Box<dyn Error>> {
-    let session_id = login_page_res
+/// Collects the cookies the login page sets, such as "ASP.NET_SessionId=..."
+/// and the anti-forgery cookie if the portal uses one, in the form of a
+/// `Cookie` header
+pub fn get_session_id(login_page_res: &Response) -> Result<String> {
+    let cookies = login_page_res
         .headers()
-        .get("set-cookie")
-        .ok_or("Cookie not found")?
-        .to_str()?
-        .split(";")
-        .next()
-        .ok_or("Invalid session cookie format")?
-        .to_string();
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|e| TimetableError::unexpected("set-cookie", e.to_string()))?
+                .split(";")
+                .next()
+                .map(str::trim)
+                .filter(|cookie| cookie.contains('='))
+                .ok_or_else(|| {
+                    TimetableError::unexpected("set-cookie", "Invalid session cookie format")
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(session_id)
+    if cookies.is_empty() {
+        return Err(TimetableError::unexpected("set-cookie", "Cookie not found"));
+    }
+
+    Ok(cookies.join("; "))
 }
 
-pub fn get_captcha_addends(login_page_html: &str) -> Result<(i8, i8), Box<dyn Error>> {
+fn selector(selectors: &'static str) -> Selector {
+    Selector::parse(selectors).expect("selectors should be valid CSS")
+}
+
+pub fn get_captcha_addends(login_page_html: &str) -> Result<(i8, i8)> {
     let doc = Html::parse_document(login_page_html);
-    let first_num_selector = Selector::parse("[name=FirstNo]")?;
+    let first_num_selector = selector("[name=FirstNo]");
     let first_num = doc
         .select(&first_num_selector)
         .next()
         .and_then(|v| v.attr("value"))
         .and_then(|v| v.parse::<i8>().ok())
-        .ok_or("Invalid first number")?;
-    let second_num_selector = Selector::parse("[name=SecondNo]")?;
+        .ok_or_else(|| TimetableError::unexpected("FirstNo", "Invalid first number"))?;
+    let second_num_selector = selector("[name=SecondNo]");
     let second_num = doc
         .select(&second_num_selector)
         .next()
         .and_then(|v| v.attr("value"))
         .and_then(|v| v.parse::<i8>().ok())
-        .ok_or("Invalid second number")?;
+        .ok_or_else(|| TimetableError::unexpected("SecondNo", "Invalid second number"))?;
 
     Ok((first_num, second_num))
 }
 
+/// Hidden fields of the login form, the one with the Username input, other
+/// than the captcha addends, such as an anti-forgery token, which have to be
+/// posted back unchanged
+pub fn get_hidden_fields(login_page_html: &str) -> Vec<(String, String)> {
+    let doc = Html::parse_document(login_page_html);
+    let form_selector = selector("form");
+    let username_selector = selector("input[name=Username]");
+    let hidden_selector = selector("input[type=hidden][name]");
+
+    // other forms on the page, such as a search box, must not add to or
+    // override the fields of the login form
+    let Some(login_form) = doc
+        .select(&form_selector)
+        .find(|form| form.select(&username_selector).next().is_some())
+    else {
+        return Vec::new();
+    };
+    login_form
+        .select(&hidden_selector)
+        .filter_map(|input| {
+            let name = input.attr("name")?;
+            (name != "FirstNo" && name != "SecondNo").then(|| {
+                (
+                    name.to_string(),
+                    input.attr("value").unwrap_or_default().to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
 pub async fn authenticate<'a>(
     client: &reqwest::Client,
+    config: &PortalConfig,
     username: &'a str,
     password: &'a str,
     first_num: i8,
     second_num: i8,
-) -> Result<String, Box<dyn Error>> {
+    hidden_fields: &[(String, String)],
+) -> Result<String> {
+    let answer = (first_num + second_num).to_string();
+    let first_num = first_num.to_string();
+    let second_num = second_num.to_string();
+    let mut fields = vec![
+        ("Username", username),
+        ("Password", password),
+        ("FirstNo", first_num.as_str()),
+        ("SecondNo", second_num.as_str()),
+        ("Answer", answer.as_str()),
+    ];
+    fields.extend(
+        hidden_fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+
+    // form encoding keeps characters like '&', '=', '+' and '%' in the
+    // password from being read as separators or escapes
     let res = client
-        .post("https://portal.ewubd.edu")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .body(format!(
-            "Username={}&Password={}&FirstNo={}&SecondNo={}&Answer={}",
-            username,
-            password,
-            first_num,
-            second_num,
-            first_num + second_num
-        ))
+        .post(&config.base_url)
+        .form(&fields)
         .send()
-        .await?;
-
-    if res.status().is_success() || res.status().is_redirection() {
-        let html = res.text().await?;
-        let doc = Html::parse_document(&html);
-
-        let error_msg_selector = Selector::parse(".error")?;
-        let error_msg = doc
-            .select(&error_msg_selector)
-            .next()
-            .map(|v| v.text().collect::<String>());
-
-        match error_msg {
-            Some(msg) => Err(msg.into()),
-            _ => {
-                let welcome_msg_selector = Selector::parse(".nav-user > span")?;
-                let welcome_msg = doc
-                    .select(&welcome_msg_selector)
-                    .next()
-                    .map(|v| v.text().collect::<String>())
-                    .ok_or("Welcome msg not found")?;
-
-                Ok(welcome_msg)
-            }
+        .await?
+        .error_for_status()?;
+
+    let html = res.text().await?;
+    let doc = Html::parse_document(&html);
+
+    let error_msg_selector = selector(".error");
+    let error_msg = doc
+        .select(&error_msg_selector)
+        .next()
+        .map(|v| v.text().collect::<String>());
+
+    match error_msg {
+        Some(msg) => Err(TimetableError::InvalidCredentials(msg)),
+        _ => {
+            let welcome_msg_selector = selector(".nav-user > span");
+            let welcome_msg = doc
+                .select(&welcome_msg_selector)
+                .next()
+                .map(|v| v.text().collect::<String>())
+                .ok_or_else(|| {
+                    TimetableError::unexpected(".nav-user > span", "Welcome msg not found")
+                })?;
+
+            Ok(welcome_msg)
         }
-    } else {
-        Err(res.text().await?.into())
     }
 }
+
+/// Runs the whole login flow and returns the authenticated session cookie
+pub async fn login(config: &PortalConfig, username: &str, password: &str) -> Result<String> {
+    let login_page_res = fetch_login_page(config).await?;
+    let session_id = get_session_id(&login_page_res)?;
+    let login_page_html = login_page_res.text().await?;
+    let (first_num, second_num) = get_captcha_addends(&login_page_html)?;
+    let hidden_fields = get_hidden_fields(&login_page_html);
+
+    let client = crate::utils::build_authenticated_client(config, &session_id)?;
+    authenticate(
+        &client,
+        config,
+        username,
+        password,
+        first_num,
+        second_num,
+        &hidden_fields,
+    )
+    .await?;
+
+    Ok(session_id)
+}
@@ -1,25 +1,74 @@
-use chrono::{Datelike, NaiveDate, NaiveTime, Utc, Weekday};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    courses::Course,
+    error::{Result, TimetableError},
+    exams::{self, Exam},
+    holidays::Holiday,
+    icalendar,
+    overrides::{Override, OverrideChange},
+    periods::{Period, Time, Weekday},
+};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use ics::{
     components::{Parameter, Property},
-    properties::{CalScale, Description, DtEnd, DtStart, Location, Method, Name, RRule, Summary},
-    Event, ICalendar, Standard, TimeZone as ICSTimeZone,
+    escape_text,
+    properties::{
+        CalScale, Categories, Description, DtEnd, DtStart, ExDate, Location, Method, Name, RDate,
+        RRule, RecurrenceID, Summary, Transp, Trigger,
+    },
+    Alarm, Event, ICalendar, Standard, TimeZone as ICSTimeZone,
 };
-use std::error::Error;
-
-use crate::courses::Course;
-
-pub fn find_first_weekday(start_date: NaiveDate, day_index: u8) -> Option<NaiveDate> {
-    let target_weekday = match day_index {
-        0 => Weekday::Sun,
-        1 => Weekday::Mon,
-        2 => Weekday::Tue,
-        3 => Weekday::Wed,
-        4 => Weekday::Thu,
-        5 => Weekday::Fri,
-        6 => Weekday::Sat,
-        _ => return None,
-    };
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Asia/Dhaka has had no daylight saving time since 2009
+pub(crate) const DHAKA_UTC_OFFSET: Duration = Duration::hours(6);
+
+/// Domain part of the UIDs of generated events
+pub const UID_DOMAIN: &str = "ewubd-timetable.invalid";
+
+/// Extra inputs for [`build_timetable`] besides the courses and semester dates
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TimetableOptions {
+    /// Portal ID of the semester, which event UIDs are derived from so that
+    /// they survive corrections to the name and dates. Without it they are
+    /// derived from the start date.
+    #[serde(default)]
+    pub semester_id: Option<u16>,
+    /// Days without classes, excluded from the weekly events and added as all-day events
+    #[serde(default)]
+    pub holidays: Vec<Holiday>,
+    /// One-off additions, cancellations and moves of classes
+    #[serde(default)]
+    pub overrides: Vec<Override>,
+    /// Added as separate events, weekly classes end before the first final exam
+    #[serde(default)]
+    pub exams: Vec<Exam>,
+    #[serde(default)]
+    pub reminders: Reminders,
+}
+
+/// How many minutes before a class its reminder goes off
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Reminders {
+    /// Lead time for lectures, `None` for no reminder
+    pub class: Option<u32>,
+    /// Lead time for labs, `None` to use the lecture lead time
+    pub lab: Option<u32>,
+}
+
+impl Reminders {
+    pub fn for_period(&self, period: &Period) -> Option<u32> {
+        match self.lab {
+            Some(lab) if period.lab => Some(lab),
+            _ => self.class,
+        }
+    }
+}
 
+pub fn find_first_weekday(start_date: NaiveDate, day: Weekday) -> Option<NaiveDate> {
+    let target_weekday = chrono::Weekday::from(day);
     let current_weekday = start_date.weekday();
 
     let days_until =
@@ -28,17 +77,275 @@ pub fn find_first_weekday(start_date: NaiveDate, day_index: u8) -> Option<NaiveD
     start_date.checked_add_signed(chrono::Duration::days(days_until as i64))
 }
 
-fn get_two_letter_weekday(day_index: u8) -> &'static str {
-    match day_index {
-        0 => "SU",
-        1 => "MO",
-        2 => "TU",
-        3 => "WE",
-        4 => "TH",
-        5 => "FR",
-        6 => "SA",
-        _ => panic!("Invalid day index"),
+/// Splits sorted days into runs of consecutive days, returned as (first, last)
+fn consecutive_runs(days: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut runs: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    for &day in days {
+        match runs.last_mut() {
+            Some((_, last)) if last.succ_opt() == Some(day) => *last = day,
+            _ => runs.push((day, day)),
+        }
+    }
+    runs
+}
+
+fn holiday_events(
+    semester_key: &str,
+    holidays: &[Holiday],
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Vec<Event<'static>> {
+    let mut events = Vec::new();
+
+    for holiday in holidays {
+        for (first, last) in consecutive_runs(&holiday.days_between(start_date, end_date)) {
+            let mut event = Event::new(
+                event_uid(&format!(
+                    "{}/holiday/{}/{}",
+                    semester_key, holiday.name, first
+                )),
+                dtstamp_now(),
+            );
+
+            let mut dtstart = DtStart::new(first.format("%Y%m%d").to_string());
+            dtstart.add(Parameter::new("VALUE", "DATE"));
+            // DTEND of an all-day event is exclusive
+            let mut dtend =
+                DtEnd::new(last.succ_opt().unwrap_or(last).format("%Y%m%d").to_string());
+            dtend.add(Parameter::new("VALUE", "DATE"));
+
+            event.push(Summary::new(escape_text(holiday.name.clone())));
+            event.push(dtstart);
+            event.push(dtend);
+            event.push(Categories::new("Holiday"));
+            event.push(Transp::transparent());
+            events.push(event);
+        }
+    }
+
+    events
+}
+
+fn exam_events(semester_key: &str, exams: &[Exam]) -> Result<Vec<Event<'static>>> {
+    let mut events = Vec::new();
+
+    for exam in exams {
+        let mut event = Event::new(
+            event_uid(&format!(
+                "{}/exam/{}/{}/{}",
+                semester_key, exam.course_code, exam.kind, exam.date
+            )),
+            dtstamp_now(),
+        );
+
+        let mut dtstart = DtStart::new(ical_datetime(
+            exam.date.and_time(naive_time(exam.start_time)?),
+        ));
+        dtstart.add(Parameter::new("TZID", "Asia/Dhaka"));
+        let mut dtend = DtEnd::new(ical_datetime(
+            exam.date.and_time(naive_time(exam.end_time)?),
+        ));
+        dtend.add(Parameter::new("TZID", "Asia/Dhaka"));
+
+        event.push(Summary::new(escape_text(format!(
+            "{} {} Exam",
+            exam.course_code, exam.kind
+        ))));
+        if let Some(room) = &exam.room {
+            event.push(Location::new(escape_text(room.clone())));
+        }
+        event.push(dtstart);
+        event.push(dtend);
+        event.push(Categories::new("Exam"));
+        events.push(event);
     }
+
+    Ok(events)
+}
+
+/// Stable UID for the event identified by `key`, which must not change between builds
+fn event_uid(key: &str) -> String {
+    format!(
+        "{:x}@{}",
+        xxhash_rust::xxh3::xxh3_64(key.as_bytes()),
+        UID_DOMAIN
+    )
+}
+
+fn dtstamp_now() -> String {
+    Utc::now().format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// An event of a generated calendar, as the lines it was written as
+struct EventLines<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> EventLines<'a> {
+    /// UID and RECURRENCE-ID, which together identify an event across builds
+    fn key(&self) -> String {
+        self.lines
+            .iter()
+            .filter(|line| line.starts_with("UID") || line.starts_with("RECURRENCE-ID"))
+            .copied()
+            .collect()
+    }
+
+    /// Hash of everything except the DTSTAMP
+    fn content_hash(&self) -> u64 {
+        let content: String = self
+            .lines
+            .iter()
+            .filter(|line| !line.starts_with("DTSTAMP"))
+            .copied()
+            .collect();
+        xxhash_rust::xxh3::xxh3_64(content.as_bytes())
+    }
+
+    fn dtstamp(&self) -> Option<&'a str> {
+        self.lines
+            .iter()
+            .find(|line| line.starts_with("DTSTAMP"))
+            .copied()
+    }
+}
+
+enum CalendarPart<'a> {
+    Line(&'a str),
+    Event(EventLines<'a>),
+}
+
+/// Splits a calendar into the lines outside of events and the events, in order
+fn split_events(ical: &str) -> Vec<CalendarPart<'_>> {
+    let mut parts = Vec::new();
+    let mut event: Option<Vec<&str>> = None;
+
+    for line in ical.split_inclusive("\r\n") {
+        match &mut event {
+            None if line == "BEGIN:VEVENT\r\n" => event = Some(vec![line]),
+            None => parts.push(CalendarPart::Line(line)),
+            Some(lines) => {
+                lines.push(line);
+                if line == "END:VEVENT\r\n" {
+                    parts.push(CalendarPart::Event(EventLines {
+                        lines: event.take().unwrap_or_default(),
+                    }));
+                }
+            }
+        }
+    }
+    // an unterminated event is passed through as is
+    if let Some(lines) = event {
+        parts.extend(lines.into_iter().map(CalendarPart::Line));
+    }
+
+    parts
+}
+
+/// Keeps the DTSTAMP of every event of `ical` whose content is unchanged since
+/// `previous` was built, so that calendar apps don't treat them as modified
+pub fn reuse_stamps(ical: &str, previous: &str) -> String {
+    let previous_events = split_events(previous)
+        .into_iter()
+        .filter_map(|part| match part {
+            CalendarPart::Event(event) => Some(event),
+            CalendarPart::Line(_) => None,
+        })
+        .filter_map(|event| Some((event.key(), (event.content_hash(), event.dtstamp()?))))
+        .collect::<HashMap<_, _>>();
+
+    let mut result = String::with_capacity(ical.len());
+    for part in split_events(ical) {
+        let event = match part {
+            CalendarPart::Line(line) => {
+                result.push_str(line);
+                continue;
+            }
+            CalendarPart::Event(event) => event,
+        };
+
+        let stamp = previous_events
+            .get(&event.key())
+            .filter(|(hash, _)| *hash == event.content_hash())
+            .map(|(_, stamp)| *stamp);
+        for line in &event.lines {
+            match stamp {
+                Some(stamp) if line.starts_with("DTSTAMP") => result.push_str(stamp),
+                _ => result.push_str(line),
+            }
+        }
+    }
+
+    result
+}
+
+fn naive_time(time: Time) -> Result<NaiveTime> {
+    NaiveTime::from_hms_opt(time.hours as u32, time.minutes as u32, 0)
+        .ok_or_else(|| TimetableError::InvalidTimeSlot(time.to_string()))
+}
+
+fn ical_datetime(datetime: NaiveDateTime) -> String {
+    datetime.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn ical_datetimes(datetimes: &[NaiveDateTime]) -> String {
+    datetimes
+        .iter()
+        .map(|datetime| ical_datetime(*datetime))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Identifies the occurrence of a weekly event that an override event replaces
+fn recurrence_id(original: NaiveDateTime) -> RecurrenceID<'static> {
+    let mut recurrence_id = RecurrenceID::new(ical_datetime(original));
+    recurrence_id.add(Parameter::new("TZID", "Asia/Dhaka"));
+    recurrence_id
+}
+
+/// Category of lab events, which is how [`crate::import`] tells them apart
+pub const LAB_CATEGORY: &str = "Lab";
+
+/// A single class of `course` without any recurrence, with a reminder
+/// `reminder` minutes before it starts
+fn class_event(
+    uid: &str,
+    course: &Course,
+    lab: bool,
+    room: &str,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    reminder: Option<u32>,
+) -> Event<'static> {
+    let mut event = Event::new(uid.to_string(), dtstamp_now());
+
+    let mut dtstart = DtStart::new(ical_datetime(start));
+    dtstart.add(Parameter::new("TZID", "Asia/Dhaka"));
+    let mut dtend = DtEnd::new(ical_datetime(end));
+    dtend.add(Parameter::new("TZID", "Asia/Dhaka"));
+
+    let summary = format!("{} ({})", course.course_code.clone(), course.section);
+
+    event.push(Summary::new(escape_text(summary.clone())));
+    event.push(Location::new(escape_text(room.to_string())));
+    event.push(Description::new(escape_text(format!(
+        "Lecturer: {}",
+        course.lecturer.clone()
+    ))));
+    event.push(dtstart);
+    event.push(dtend);
+    if lab {
+        event.push(Categories::new(LAB_CATEGORY));
+    }
+
+    if let Some(minutes) = reminder {
+        event.add_alarm(Alarm::display(
+            Trigger::new(format!("-PT{}M", minutes)),
+            Description::new(escape_text(format!("{} in {}", summary, room))),
+        ));
+    }
+
+    event
 }
 
 pub fn build_timetable(
@@ -46,7 +353,8 @@ pub fn build_timetable(
     name: &str,
     start_date: NaiveDate,
     end_date: NaiveDate,
-) -> Result<String, Box<dyn Error>> {
+    options: &TimetableOptions,
+) -> Result<String> {
     let mut calendar = ICalendar::new("2.0", format!("-//East West University//{}//EN", name));
 
     let timezone = ICSTimeZone::standard(
@@ -59,79 +367,637 @@ pub fn build_timetable(
     calendar.push(CalScale::new("GREGORIAN"));
     calendar.push(Method::new("PUBLISH"));
 
+    let holiday_days: BTreeSet<NaiveDate> = options
+        .holidays
+        .iter()
+        .flat_map(|holiday| holiday.days_between(start_date, end_date))
+        .collect();
+
+    // weekly classes stop before the final exam period
+    let classes_end = exams::finals_start(&options.exams)
+        .and_then(|first_final| first_final.pred_opt())
+        .map_or(end_date, |last_class| last_class.min(end_date));
+
+    // UIDs are keyed by the semester rather than the calendar name or dates,
+    // which can be edited, so that correcting them doesn't duplicate events
+    let semester_key = match options.semester_id {
+        Some(semester_id) => semester_id.to_string(),
+        None => start_date.to_string(),
+    };
+    let mut matched = vec![false; options.overrides.len()];
+
     for course in courses {
-        for period in course.periods {
-            let ev_hash = xxhash_rust::xxh3::xxh3_64(
-                format!(
-                    "{}{}{}",
-                    course.course_code.clone(),
-                    period.room.clone(),
-                    period.day
-                )
-                .as_bytes(),
-            );
+        // extra sessions are added to the course's first weekly event that
+        // has any classes, which isn't necessarily its first period
+        let mut extras_added = false;
 
-            let mut event = Event::new(
-                format!("{:x}", ev_hash),
-                Utc::now().format("%Y%m%dT000000").to_string(),
+        for period in &course.periods {
+            let uid = event_uid(&format!(
+                "{}/{}/{}/{}/{}",
+                semester_key,
+                course.course_code,
+                course.section,
+                period.day.index(),
+                period.start_time
+            ));
+
+            let course_start_date =
+                find_first_weekday(start_date, period.day).ok_or_else(|| {
+                    TimetableError::InvalidTimeSlot(format!(
+                        "first {} from {}",
+                        period.day, start_date
+                    ))
+                })?;
+            let occurrences = course_start_date
+                .iter_weeks()
+                .take_while(|day| *day <= classes_end)
+                .collect::<Vec<_>>();
+            if occurrences.is_empty() {
+                // DTSTART would otherwise still count as a class
+                continue;
+            }
+
+            let period_start = naive_time(period.start_time)?;
+            let period_end = naive_time(period.end_time)?;
+            let reminder = options.reminders.for_period(period);
+
+            let mut event = class_event(
+                &uid,
+                &course,
+                period.lab,
+                &period.room,
+                course_start_date.and_time(period_start),
+                course_start_date.and_time(period_end),
+                reminder,
             );
 
-            let course_start_date = find_first_weekday(start_date, period.day)
-                .ok_or("Error finding first date of course")?;
+            // UNTIL has to be in UTC since DTSTART has a TZID
+            let until = classes_end.and_time(NaiveTime::MIN) + Duration::days(1)
+                - Duration::seconds(1)
+                - DHAKA_UTC_OFFSET;
+            event.push(RRule::new(format!(
+                "FREQ=WEEKLY;BYDAY={};UNTIL={}Z",
+                period.day.two_letter(),
+                ical_datetime(until)
+            )));
+
+            let mut skipped = occurrences
+                .iter()
+                .filter(|day| holiday_days.contains(day))
+                .map(|day| day.and_time(period_start))
+                .collect::<Vec<_>>();
+            let mut added = Vec::new();
+            let mut moved_from = Vec::new();
+            let mut changed = Vec::new();
+
+            for (index, change) in options.overrides.iter().enumerate() {
+                if change.course_code != course.course_code {
+                    continue;
+                }
+
+                let original = change.date.and_time(period_start);
+                match &change.change {
+                    OverrideChange::Cancel if occurrences.contains(&change.date) => {
+                        skipped.push(original);
+                    }
+                    OverrideChange::Move {
+                        to_date,
+                        times,
+                        room,
+                    } if occurrences.contains(&change.date) => {
+                        let (start_time, end_time) =
+                            times.unwrap_or((period.start_time, period.end_time));
+                        let date = to_date.unwrap_or(change.date);
+
+                        let mut moved = class_event(
+                            &uid,
+                            &course,
+                            period.lab,
+                            room.as_deref().unwrap_or(&period.room),
+                            date.and_time(naive_time(start_time)?),
+                            date.and_time(naive_time(end_time)?),
+                            reminder,
+                        );
+                        moved.push(recurrence_id(original));
+                        changed.push(moved);
+                        moved_from.push(original);
+                    }
+                    OverrideChange::Add {
+                        start_time,
+                        end_time,
+                        room,
+                    } if !extras_added => {
+                        let start = change.date.and_time(naive_time(*start_time)?);
+                        let end = change.date.and_time(naive_time(*end_time)?);
+                        let room = room.as_deref().unwrap_or(&period.room);
+                        added.push(start);
+
+                        // an RDATE takes the duration and room of the weekly event
+                        if end - start != period_end - period_start || room != period.room {
+                            let mut extra =
+                                class_event(&uid, &course, period.lab, room, start, end, reminder);
+                            extra.push(recurrence_id(start));
+                            changed.push(extra);
+                        }
+                    }
+                    _ => continue,
+                }
+                matched[index] = true;
+            }
+            extras_added = true;
+
+            // a class moved off a holiday still takes place, and an EXDATE
+            // would hide the moved occurrence in most calendar apps
+            skipped.retain(|day| !moved_from.contains(day));
+            if !skipped.is_empty() {
+                skipped.sort();
+                skipped.dedup();
+                let mut exdate = ExDate::new(ical_datetimes(&skipped));
+                exdate.add(Parameter::new("TZID", "Asia/Dhaka"));
+                event.push(exdate);
+            }
+            if !added.is_empty() {
+                added.sort();
+                added.dedup();
+                let mut rdate = RDate::new(ical_datetimes(&added));
+                rdate.add(Parameter::new("TZID", "Asia/Dhaka"));
+                event.push(rdate);
+            }
+
+            calendar.add_event(event);
+            for event in changed {
+                calendar.add_event(event);
+            }
+        }
+    }
+
+    if let Some(index) = matched.iter().position(|matched| !matched) {
+        return Err(TimetableError::UnmatchedOverride(
+            options.overrides[index].to_string(),
+        ));
+    }
+
+    for event in holiday_events(&semester_key, &options.holidays, start_date, end_date) {
+        calendar.add_event(event);
+    }
+    for event in exam_events(&semester_key, &options.exams)? {
+        calendar.add_event(event);
+    }
+
+    let ical = icalendar::fold(&calendar.to_string());
+    icalendar::validate(&ical)?;
+    Ok(ical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::date;
+    use crate::{holidays::parse_holidays, overrides::parse_overrides, periods::Period};
 
-            let period_start = NaiveTime::from_hms_opt(
-                period.start_time.hours as u32,
-                period.start_time.minutes as u32,
-                0,
+    fn cse101() -> Vec<Course> {
+        vec![Course {
+            course_code: "CSE101".to_string(),
+            section: 2,
+            lecturer: "Jane Doe".to_string(),
+            periods: vec![Period {
+                day: Weekday::Monday,
+                start_time: Time::new(8, 30),
+                end_time: Time::new(10, 0),
+                room: "AB3-302".to_string(),
+                lab: false,
+            }],
+        }]
+    }
+
+    #[test]
+    fn holidays_are_excluded_and_added() {
+        let courses = cse101();
+        let options = TimetableOptions {
+            holidays: parse_holidays(
+                "2024-10-12..2024-10-14, Durga Puja\n*-12-16, Victory Day\n*-02-21, Not in semester",
             )
-            .ok_or("Invalid period start time")?;
-            let period_end = NaiveTime::from_hms_opt(
-                period.end_time.hours as u32,
-                period.end_time.minutes as u32,
-                0,
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let ical = build_timetable(
+            courses,
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
+
+        assert!(ical.contains("EXDATE;TZID=Asia/Dhaka:20241014T083000,20241216T083000\r\n"));
+        assert!(ical.contains(
+            "SUMMARY:Durga Puja\r\nDTSTART;VALUE=DATE:20241012\r\nDTEND;VALUE=DATE:20241015\r\n"
+        ));
+        assert!(ical.contains("SUMMARY:Victory Day\r\n"));
+        assert!(!ical.contains("Not in semester"));
+        assert!(ical.contains("TRANSP:TRANSPARENT"));
+    }
+
+    #[test]
+    fn long_exdate_list_is_folded() {
+        let options = TimetableOptions {
+            holidays: parse_holidays("2024-10-01..2024-11-30, Campus closure").unwrap(),
+            ..Default::default()
+        };
+
+        let ical = build_timetable(
+            cse101(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
+
+        let exdate = ical
+            .split("\r\n")
+            .skip_while(|line| !line.starts_with("EXDATE"))
+            .take_while(|line| line.starts_with("EXDATE") || line.starts_with(' '))
+            .collect::<Vec<_>>();
+        assert!(exdate.len() > 1);
+        assert!(exdate.iter().all(|line| line.len() <= 75));
+    }
+
+    #[test]
+    fn no_exdate_without_holidays() {
+        let ical = build_timetable(
+            Vec::new(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &TimetableOptions::default(),
+        )
+        .unwrap();
+
+        assert!(!ical.contains("EXDATE"));
+        assert!(!ical.contains("BEGIN:VEVENT"));
+    }
+
+    #[test]
+    fn overrides_are_rendered() {
+        let options = TimetableOptions {
+            holidays: parse_holidays("2024-12-16, Victory Day").unwrap(),
+            overrides: parse_overrides(
+                "CSE101, 2024-10-21, cancel\n\
+                 CSE101, 2024-10-28, move 2024-10-30 1:00PM-2:30PM\n\
+                 CSE101, 2024-11-02, add 8:30AM-10:00AM\n\
+                 CSE101, 2024-11-09, add 11:00AM-1:00PM @ AB1-201",
             )
-            .ok_or("Invalid period end time")?;
+            .unwrap(),
+            ..Default::default()
+        };
 
-            let mut dtstart = DtStart::new(
-                course_start_date
-                    .and_time(period_start)
-                    .format("%Y%m%dT%H%M%S")
-                    .to_string(),
-            );
-            dtstart.add(Parameter::new("TZID", "Asia/Dhaka"));
+        let ical = build_timetable(
+            cse101(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
 
-            let mut dtend = DtEnd::new(
-                course_start_date
-                    .and_time(period_end)
-                    .format("%Y%m%dT%H%M%S")
-                    .to_string(),
-            );
-            dtend.add(Parameter::new("TZID", "Asia/Dhaka"));
+        assert!(ical.contains("EXDATE;TZID=Asia/Dhaka:20241021T083000,20241216T083000\r\n"));
+        assert!(ical.contains("RDATE;TZID=Asia/Dhaka:20241102T083000,20241109T110000\r\n"));
+        assert!(ical.contains(
+            "DTSTART;TZID=Asia/Dhaka:20241030T130000\r\n\
+             DTEND;TZID=Asia/Dhaka:20241030T143000\r\n\
+             RECURRENCE-ID;TZID=Asia/Dhaka:20241028T083000\r\n"
+        ));
+        assert!(ical.contains(
+            "LOCATION:AB1-201\r\nDESCRIPTION:Lecturer: Jane Doe\r\n\
+             DTSTART;TZID=Asia/Dhaka:20241109T110000\r\n\
+             DTEND;TZID=Asia/Dhaka:20241109T130000\r\n\
+             RECURRENCE-ID;TZID=Asia/Dhaka:20241109T110000\r\n"
+        ));
+        // the make-up class on Nov 2 fits the weekly event and needs no override
+        assert!(!ical.contains("RECURRENCE-ID;TZID=Asia/Dhaka:20241102T083000"));
+    }
 
-            let mut rrule = RRule::new("FREQ=WEEKLY");
-            rrule.add(Parameter::new("BYDAY", get_two_letter_weekday(period.day)));
-            rrule.add(Parameter::new(
-                "UNTIL",
-                end_date.format("%Y%m%d").to_string(),
-            ));
+    #[test]
+    fn class_moved_off_holiday_is_not_excluded() {
+        let options = TimetableOptions {
+            holidays: parse_holidays("2024-10-14, Durga Puja\n2024-12-16, Victory Day").unwrap(),
+            overrides: parse_overrides("CSE101, 2024-10-14, move 2024-10-19").unwrap(),
+            ..Default::default()
+        };
 
-            event.push(Summary::new(format!(
-                "{} ({})",
-                course.course_code.clone(),
-                course.section
-            )));
-            event.push(Location::new(period.room.clone()));
-            event.push(Description::new(format!(
-                "Lecturer: {}",
-                course.lecturer.clone()
-            )));
-            event.push(dtstart);
-            event.push(dtend);
-            event.push(rrule);
+        let ical = build_timetable(
+            cse101(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
 
-            calendar.add_event(event);
+        assert!(ical.contains("EXDATE;TZID=Asia/Dhaka:20241216T083000\r\n"));
+        assert!(ical.contains(
+            "DTSTART;TZID=Asia/Dhaka:20241019T083000\r\n\
+             DTEND;TZID=Asia/Dhaka:20241019T100000\r\n\
+             RECURRENCE-ID;TZID=Asia/Dhaka:20241014T083000\r\n"
+        ));
+    }
+
+    #[test]
+    fn extra_session_goes_to_first_period_with_classes() {
+        let mut courses = cse101();
+        courses[0].periods.insert(
+            0,
+            Period {
+                day: Weekday::Sunday,
+                start_time: Time::new(8, 30),
+                end_time: Time::new(10, 0),
+                room: "AB3-302".to_string(),
+                lab: false,
+            },
+        );
+        let options = TimetableOptions {
+            overrides: parse_overrides("CSE101, 2024-09-04, add 8:30AM-10:00AM").unwrap(),
+            ..Default::default()
+        };
+
+        // the semester has no Sunday, only a Monday
+        let ical = build_timetable(
+            courses,
+            "Fall 2024",
+            date(2024, 9, 2),
+            date(2024, 9, 7),
+            &options,
+        )
+        .unwrap();
+
+        assert!(ical.contains("RDATE;TZID=Asia/Dhaka:20240904T083000\r\n"));
+    }
+
+    #[test]
+    fn unmatched_override_is_an_error() {
+        for text in [
+            // CSE101 has no class on Tuesdays
+            "CSE101, 2024-10-22, cancel",
+            "CSE101, 2025-01-06, move 2025-01-07",
+            "MAT101, 2024-11-02, add 8:30AM-10:00AM",
+        ] {
+            let options = TimetableOptions {
+                overrides: parse_overrides(text).unwrap(),
+                ..Default::default()
+            };
+            let err = build_timetable(
+                cse101(),
+                "Fall 2024",
+                date(2024, 9, 1),
+                date(2024, 12, 19),
+                &options,
+            )
+            .unwrap_err();
+
+            assert!(
+                matches!(err, TimetableError::UnmatchedOverride(_)),
+                "{}: {:?}",
+                text,
+                err
+            );
         }
     }
 
-    Ok(calendar.to_string())
+    #[test]
+    fn exams_are_added_and_end_classes() {
+        let options = TimetableOptions {
+            exams: exams::parse_exams(
+                "CSE101,midterm,2024-10-27,8:30AM,10:00AM\n\
+                 CSE101,final,2024-12-14,9:00AM,11:00AM,AB3-302",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let ical = build_timetable(
+            cse101(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
+
+        assert!(ical.contains("RRULE:FREQ=WEEKLY;BYDAY=MO;UNTIL=20241213T175959Z\r\n"));
+        assert!(ical.contains(
+            "SUMMARY:CSE101 Final Exam\r\nLOCATION:AB3-302\r\n\
+             DTSTART;TZID=Asia/Dhaka:20241214T090000\r\n\
+             DTEND;TZID=Asia/Dhaka:20241214T110000\r\n\
+             CATEGORIES:Exam\r\n"
+        ));
+        assert!(ical.contains("SUMMARY:CSE101 Midterm Exam\r\n"));
+    }
+
+    #[test]
+    fn reminders_are_added() {
+        let mut courses = cse101();
+        courses[0].periods.push(Period {
+            day: Weekday::Thursday,
+            start_time: Time::new(10, 10),
+            end_time: Time::new(12, 10),
+            room: "630".to_string(),
+            lab: true,
+        });
+        let options = TimetableOptions {
+            overrides: parse_overrides("CSE101, 2024-10-21, move @ AB1-201").unwrap(),
+            reminders: Reminders {
+                class: Some(10),
+                lab: Some(30),
+            },
+            ..Default::default()
+        };
+
+        let ical = build_timetable(
+            courses,
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
+
+        let alarm = |minutes: u32, room: &str| {
+            format!(
+                "BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT{}M\r\n\
+                 DESCRIPTION:CSE101 (2) in {}\r\nEND:VALARM\r\n",
+                minutes, room
+            )
+        };
+        assert!(ical.contains(&alarm(10, "AB3-302")));
+        assert!(ical.contains(&alarm(30, "630")));
+        // the moved class keeps its reminder
+        assert!(ical.contains(&alarm(10, "AB1-201")));
+    }
+
+    #[test]
+    fn no_alarm_without_reminders() {
+        let ical = build_timetable(
+            cse101(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &TimetableOptions {
+                reminders: Reminders {
+                    class: None,
+                    lab: Some(30),
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(!ical.contains("VALARM"));
+    }
+
+    #[test]
+    fn uids_are_stable_and_distinct() {
+        let mut courses = cse101();
+        courses.push(Course {
+            section: 3,
+            ..cse101().remove(0)
+        });
+        let options = TimetableOptions {
+            semester_id: Some(1),
+            ..Default::default()
+        };
+        let uids = |courses, name, start_date| {
+            build_timetable(courses, name, start_date, date(2024, 12, 19), &options)
+                .unwrap()
+                .lines()
+                .filter(|line| line.starts_with("UID:"))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        let first = uids(courses, "Fall 2024", date(2024, 9, 1));
+        assert_eq!(first.len(), 2);
+        assert_ne!(first[0], first[1]);
+        assert!(first[0].ends_with(&format!("@{}", UID_DOMAIN)));
+
+        let mut courses = cse101();
+        courses.push(Course {
+            section: 3,
+            ..cse101().remove(0)
+        });
+        // renaming the calendar and correcting its start keeps the UIDs
+        assert_eq!(uids(courses, "My classes", date(2024, 8, 31)), first);
+    }
+
+    #[test]
+    fn unchanged_events_keep_their_stamp() {
+        let build = |overrides: &str| {
+            build_timetable(
+                cse101(),
+                "Fall 2024",
+                date(2024, 9, 1),
+                date(2024, 12, 19),
+                &TimetableOptions {
+                    holidays: parse_holidays("2024-12-16, Victory Day").unwrap(),
+                    overrides: parse_overrides(overrides).unwrap(),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let old_stamp = "DTSTAMP:20240801T120000Z\r\n";
+        let previous = build("CSE101, 2024-10-21, cancel")
+            .split_inclusive("\r\n")
+            .map(|line| {
+                if line.starts_with("DTSTAMP") {
+                    old_stamp
+                } else {
+                    line
+                }
+            })
+            .collect::<String>();
+
+        let same = reuse_stamps(&build("CSE101, 2024-10-21, cancel"), &previous);
+        assert_eq!(same, previous);
+
+        // only the class event changes, the holiday keeps its stamp
+        let changed = reuse_stamps(&build("CSE101, 2024-10-28, cancel"), &previous);
+        assert_eq!(changed.matches(old_stamp).count(), 1);
+        assert!(changed.contains(&format!("{}SUMMARY:Victory Day", old_stamp)));
+    }
+
+    /// Parses the calendar back and returns the class instances of the weekly event
+    /// of `summary`, and the moved instances as (original, new start)
+    fn class_instances(
+        ical: &str,
+        summary: &str,
+    ) -> (Vec<NaiveDateTime>, Vec<(NaiveDateTime, NaiveDateTime)>) {
+        let calendar = icalendar::parse(ical).unwrap();
+        let events = calendar
+            .components("VEVENT")
+            .filter(|event| event.property("SUMMARY").unwrap().value == summary)
+            .collect::<Vec<_>>();
+        let local =
+            |property: &icalendar::Property| match icalendar::date_values(property).unwrap()[0] {
+                icalendar::DateValue::Local(datetime) => datetime,
+                value => panic!("unexpected value {:?}", value),
+            };
+
+        let weekly = events
+            .iter()
+            .find(|event| event.property("RRULE").is_some())
+            .unwrap();
+        let moved = events
+            .iter()
+            .filter_map(|event| {
+                let original = event.property("RECURRENCE-ID")?;
+                Some((local(original), local(event.property("DTSTART").unwrap())))
+            })
+            .collect();
+
+        (icalendar::instances(&calendar, weekly).unwrap(), moved)
+    }
+
+    #[test]
+    fn every_occurrence_is_scheduled() {
+        let options = TimetableOptions {
+            holidays: parse_holidays("2024-10-12..2024-10-14, Durga Puja").unwrap(),
+            overrides: parse_overrides(
+                "CSE101, 2024-10-21, cancel\n\
+                 CSE101, 2024-10-28, move 2024-10-30\n\
+                 CSE101, 2024-11-02, add 8:30AM-10:00AM",
+            )
+            .unwrap(),
+            exams: exams::parse_exams("CSE101,final,2024-12-14,9:00AM,11:00AM").unwrap(),
+            ..Default::default()
+        };
+
+        let ical = build_timetable(
+            cse101(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
+        let (instances, moved) = class_instances(&ical, "CSE101 (2)");
+
+        let at = |day: NaiveDate| day.and_hms_opt(8, 30, 0).unwrap();
+        let mut expected = date(2024, 9, 2)
+            .iter_weeks()
+            .take_while(|day| *day < date(2024, 12, 14))
+            .filter(|day| ![date(2024, 10, 14), date(2024, 10, 21)].contains(day))
+            .map(at)
+            .collect::<Vec<_>>();
+        expected.push(at(date(2024, 11, 2)));
+        expected.sort();
+
+        assert_eq!(instances, expected);
+        assert_eq!(expected.last(), Some(&at(date(2024, 12, 9))));
+        assert_eq!(
+            moved,
+            vec![(at(date(2024, 10, 28)), at(date(2024, 10, 30)))]
+        );
+    }
 }
@@ -0,0 +1,238 @@
+use std::fmt::Display;
+
+use crate::{courses::Course, periods::Period};
+
+/// Shortest gap in minutes that leaves time to walk to a class in another building
+pub const BUILDING_CHANGE_MINUTES: u16 = 10;
+
+/// A period together with the course it belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub course_code: String,
+    pub period: Period,
+}
+
+/// A problem with a set of courses taken together, as found by [`find_conflicts`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// Two classes on the same day take place at the same time
+    Overlap(Class, Class),
+    /// A class in another building starts too soon after the previous one ends
+    NoBuffer(Class, Class),
+    /// The same course is taken in more than one section
+    DuplicateEnrollment {
+        course_code: String,
+        sections: Vec<u8>,
+    },
+}
+
+impl Conflict {
+    /// Whether the period of the course is one of the conflicting ones. Every
+    /// period of a course taken twice is.
+    pub fn involves(&self, course_code: &str, period: &Period) -> bool {
+        match self {
+            Conflict::Overlap(first, second) | Conflict::NoBuffer(first, second) => [first, second]
+                .into_iter()
+                .any(|class| class.course_code == course_code && class.period == *period),
+            Conflict::DuplicateEnrollment {
+                course_code: code, ..
+            } => code == course_code,
+        }
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conflict::Overlap(first, second) => write!(
+                f,
+                "{} ({}–{}) and {} ({}–{}) overlap on {}",
+                first.course_code,
+                first.period.start_time,
+                first.period.end_time,
+                second.course_code,
+                second.period.start_time,
+                second.period.end_time,
+                first.period.day
+            ),
+            Conflict::NoBuffer(first, second) => write!(
+                f,
+                "No time to get from {} in {} to {} in {} between {} and {} on {}",
+                first.course_code,
+                first.period.room,
+                second.course_code,
+                second.period.room,
+                first.period.end_time,
+                second.period.start_time,
+                first.period.day
+            ),
+            Conflict::DuplicateEnrollment {
+                course_code,
+                sections,
+            } => {
+                let sections = sections
+                    .iter()
+                    .map(|section| section.to_string())
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "{} is taken in sections {}",
+                    course_code,
+                    sections.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Building part of a room such as "AB3" in "AB3-302", if the room names one
+fn building(room: &str) -> Option<&str> {
+    room.split_once('-')
+        .map(|(building, _)| building.trim())
+        .filter(|building| !building.is_empty())
+}
+
+/// Finds overlapping classes, back-to-back classes in different buildings
+/// with less than [`BUILDING_CHANGE_MINUTES`] between them, and courses taken
+/// in more than one section. Rooms without a building such as "630" are never
+/// reported as too far away.
+pub fn find_conflicts(courses: &[Course]) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+
+    let mut codes = Vec::<&str>::new();
+    for course in courses {
+        if codes.contains(&course.course_code.as_str()) {
+            continue;
+        }
+        codes.push(&course.course_code);
+
+        let mut sections = courses
+            .iter()
+            .filter(|c| c.course_code == course.course_code)
+            .map(|c| c.section)
+            .collect::<Vec<_>>();
+        sections.sort();
+        sections.dedup();
+        if sections.len() > 1 {
+            conflicts.push(Conflict::DuplicateEnrollment {
+                course_code: course.course_code.clone(),
+                sections,
+            });
+        }
+    }
+
+    let mut classes = courses
+        .iter()
+        .flat_map(|course| {
+            course.periods.iter().map(|period| Class {
+                course_code: course.course_code.clone(),
+                period: period.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    classes.sort_by_key(|class| (class.period.day, class.period.start_time.minutes()));
+
+    for (index, first) in classes.iter().enumerate() {
+        for second in &classes[index + 1..] {
+            if second.period.day != first.period.day {
+                break;
+            }
+
+            let gap =
+                second.period.start_time.minutes() as i32 - first.period.end_time.minutes() as i32;
+            if gap < 0 {
+                conflicts.push(Conflict::Overlap(first.clone(), second.clone()));
+            } else if gap < BUILDING_CHANGE_MINUTES as i32 {
+                if let (Some(from), Some(to)) =
+                    (building(&first.period.room), building(&second.period.room))
+                {
+                    if from != to {
+                        conflicts.push(Conflict::NoBuffer(first.clone(), second.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::periods::Weekday;
+    use crate::test_utils::period;
+
+    fn course(code: &str, section: u8, periods: Vec<Period>) -> Course {
+        Course {
+            course_code: code.to_string(),
+            section,
+            lecturer: String::new(),
+            periods,
+        }
+    }
+
+    fn class(code: &str, period: Period) -> Class {
+        Class {
+            course_code: code.to_string(),
+            period,
+        }
+    }
+
+    #[test]
+    fn no_conflicts_with_usual_gaps() {
+        let courses = vec![
+            course(
+                "CSE101",
+                2,
+                vec![period(Weekday::Monday, (8, 30), (10, 0), "AB3-302")],
+            ),
+            course(
+                "MAT101",
+                5,
+                vec![
+                    period(Weekday::Monday, (10, 10), (11, 40), "FUB-201"),
+                    period(Weekday::Tuesday, (8, 30), (10, 0), "FUB-201"),
+                ],
+            ),
+            course(
+                "CSE101L",
+                2,
+                vec![period(Weekday::Monday, (11, 40), (13, 40), "630")],
+            ),
+        ];
+        assert_eq!(find_conflicts(&courses), vec![]);
+    }
+
+    #[test]
+    fn finds_every_kind_of_conflict() {
+        let cse = period(Weekday::Monday, (8, 30), (10, 0), "AB3-302");
+        let mat = period(Weekday::Monday, (9, 0), (10, 30), "FUB-201");
+        let phy = period(Weekday::Monday, (10, 30), (12, 0), "AB1-101");
+        let courses = vec![
+            course("CSE101", 2, vec![cse.clone()]),
+            course("MAT101", 5, vec![mat.clone()]),
+            course("PHY101", 1, vec![phy.clone()]),
+            course("CSE101", 3, vec![]),
+        ];
+
+        let conflicts = find_conflicts(&courses);
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict::DuplicateEnrollment {
+                    course_code: "CSE101".to_string(),
+                    sections: vec![2, 3],
+                },
+                Conflict::Overlap(class("CSE101", cse.clone()), class("MAT101", mat.clone())),
+                Conflict::NoBuffer(class("MAT101", mat.clone()), class("PHY101", phy.clone())),
+            ]
+        );
+        assert_eq!(
+            conflicts[2].to_string(),
+            "No time to get from MAT101 in FUB-201 to PHY101 in AB1-101 between 10:30AM and 10:30AM on Monday"
+        );
+        assert!(conflicts[1].involves("MAT101", &mat));
+        assert!(!conflicts[1].involves("PHY101", &phy));
+    }
+}
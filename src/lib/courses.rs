@@ -1,9 +1,16 @@
+use std::collections::HashMap;
+
 use reqwest::Client;
-use std::error::Error;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::periods::Period;
+use crate::{
+    error::{Result, TimetableError},
+    periods::Period,
+    portal::PortalConfig,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Course {
     pub course_code: String,
     pub section: u8,
@@ -13,13 +20,14 @@ pub struct Course {
 
 pub async fn fetch_courses_as_json(
     client: &Client,
+    config: &PortalConfig,
     semester_id: u16,
-) -> Result<serde_json::Value, Box<dyn Error>> {
+) -> Result<serde_json::Value> {
     let res = client
-        .get(format!(
-        "https://portal.ewubd.edu/api/Advising/GetSemesterStudentWiseAdvisingCourseListStudent/{}",
-        semester_id
-    ))
+        .get(config.url(&format!(
+            "/api/Advising/GetSemesterStudentWiseAdvisingCourseListStudent/{}",
+            semester_id
+        )))
         .send()
         .await?
         .error_for_status()?;
@@ -28,57 +36,114 @@ pub async fn fetch_courses_as_json(
     Ok(json)
 }
 
-pub fn parse_courses(courses_json: serde_json::Value) -> Result<Vec<Course>, Box<dyn Error>> {
-    let courses_json_array = courses_json
-        .as_array()
-        .ok_or("Invalid course JSON response")?;
+fn is_yes(status: &str) -> bool {
+    status.eq_ignore_ascii_case("yes")
+}
+
+/// A single advising record as sent by `GetSemesterStudentWiseAdvisingCourseListStudent`.
+/// A course with separate theory and lab slots is sent as multiple records.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AdvisingRecord {
+    pub course_code: String,
+    #[serde(default)]
+    pub course_title: Option<String>,
+    #[serde(default)]
+    pub credits: Option<f32>,
+    pub section_name: u8,
+    pub time_slot_name: String,
+    pub room_name: String,
+    pub faculty_name: String,
+    #[serde(default)]
+    pub faculty_short_name: Option<String>,
+    /// "Yes" if the course was dropped
+    pub drop_status: String,
+    /// "Yes" if the course was withdrawn
+    #[serde(rename = "WithDrawStatus")]
+    pub withdraw_status: String,
+    /// Any other fields sent by the portal
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl AdvisingRecord {
+    /// Whether the course is still being taken, i.e. neither dropped nor withdrawn
+    pub fn is_active(&self) -> bool {
+        !is_yes(&self.drop_status) && !is_yes(&self.withdraw_status)
+    }
+
+    /// Whether the record is the lab part of a course, going by a title like "... Lab"
+    pub fn is_lab(&self) -> bool {
+        self.course_title.as_deref().is_some_and(|title| {
+            title.split_whitespace().any(|word| {
+                word.eq_ignore_ascii_case("lab") || word.eq_ignore_ascii_case("laboratory")
+            })
+        })
+    }
+}
+
+pub fn parse_advising_records(courses_json: serde_json::Value) -> Result<Vec<AdvisingRecord>> {
+    let courses_json_array = match courses_json {
+        serde_json::Value::Array(array) => array,
+        _ => return Err(TimetableError::unexpected("courses", "Expected an array")),
+    };
 
+    courses_json_array
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| {
+            let course_code = record["CourseCode"]
+                .as_str()
+                .unwrap_or("unknown course")
+                .to_string();
+            serde_path_to_error::deserialize(record).map_err(|e| {
+                TimetableError::unexpected(
+                    format!("courses[{}].{}", index, e.path()),
+                    format!("{} (record for {})", e.inner(), course_code),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Turns the active advising records into courses, merging the records of a
+/// course's theory and lab. Time slots that can't be parsed are skipped.
+pub fn parse_courses(courses_json: serde_json::Value) -> Result<Vec<Course>> {
     let mut parsed_courses = Vec::<Course>::new();
 
-    for course_json in courses_json_array {
-        let dropped = course_json["DropStatus"]
-            .as_str()
-            .ok_or("Invalid drop status")?
-            .to_lowercase()
-            == "yes";
-        let withdrawn = course_json["WithDrawStatus"]
-            .as_str()
-            .ok_or("Invalid withdrawn status")?
-            .to_lowercase()
-            == "yes";
-
-        if dropped || withdrawn {
+    for record in parse_advising_records(courses_json)? {
+        if !record.is_active() {
             continue;
         }
 
-        let time_slot_name = course_json["TimeSlotName"]
-            .as_str()
-            .ok_or("Invalid time slot name")?;
-        let room_name = course_json["RoomName"]
-            .as_str()
-            .ok_or("Invalid room name")?;
-        let course_code = course_json["CourseCode"]
-            .as_str()
-            .ok_or("Invalid course code")?;
-        let section_name = course_json["SectionName"]
-            .as_i64()
-            .ok_or("Invalid section")? as u8;
-        let faculty_name = course_json["FacultyName"]
-            .as_str()
-            .ok_or("Invalid faculty name")?;
-
-        let mut periods = Period::parse_periods(time_slot_name, room_name)?;
+        // a slot such as "TBA" shouldn't hide the rest of the timetable, the
+        // course is kept without the slot's periods
+        let mut periods = match Period::parse_periods(&record.time_slot_name, &record.room_name) {
+            Ok(periods) => periods,
+            Err(e) => {
+                log::warn!(
+                    "Skipping time slot of {} section {}: {}",
+                    record.course_code,
+                    record.section_name,
+                    e
+                );
+                Vec::new()
+            }
+        };
+        for period in &mut periods {
+            period.lab = record.is_lab();
+        }
 
         let existing_course = parsed_courses
             .iter_mut()
-            .find(|c| c.course_code == course_code && c.section == section_name);
+            .find(|c| c.course_code == record.course_code && c.section == record.section_name);
 
         match existing_course {
             None => {
                 let course = Course {
-                    course_code: course_code.to_string(),
-                    section: section_name,
-                    lecturer: faculty_name.to_string(),
+                    course_code: record.course_code,
+                    section: record.section_name,
+                    lecturer: record.faculty_name,
                     periods,
                 };
                 parsed_courses.push(course);
@@ -93,9 +158,106 @@ pub fn parse_courses(courses_json: serde_json::Value) -> Result<Vec<Course>, Box
     Ok(parsed_courses)
 }
 
-pub async fn get_courses(client: &Client, semester_id: u16) -> Result<Vec<Course>, Box<dyn Error>> {
-    let courses_json = fetch_courses_as_json(client, semester_id).await?;
+pub async fn get_courses(
+    client: &Client,
+    config: &PortalConfig,
+    semester_id: u16,
+) -> Result<Vec<Course>> {
+    let courses_json = fetch_courses_as_json(client, config, semester_id).await?;
     let courses = parse_courses(courses_json)?;
 
     Ok(courses)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(code: &str) -> serde_json::Value {
+        json!({
+            "CourseCode": code,
+            "CourseTitle": "Structured Programming",
+            "Credits": 3.0,
+            "SectionName": 2,
+            "TimeSlotName": "MW 8:30AM-10:00AM",
+            "RoomName": "AB3-302",
+            "FacultyName": "Jane Doe",
+            "FacultyShortName": "JD",
+            "DropStatus": "No",
+            "WithDrawStatus": "no",
+            "SemesterId": 1
+        })
+    }
+
+    #[test]
+    fn parses_advising_record() {
+        let records = parse_advising_records(json!([record("CSE101")])).unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].course_title.as_deref(),
+            Some("Structured Programming")
+        );
+        assert_eq!(records[0].credits, Some(3.0));
+        assert_eq!(records[0].faculty_short_name.as_deref(), Some("JD"));
+        assert_eq!(records[0].extra["SemesterId"], json!(1));
+        assert!(records[0].is_active());
+    }
+
+    #[test]
+    fn malformed_record_names_record_and_field() {
+        let mut bad = record("MAT101");
+        bad["SectionName"] = json!("two");
+
+        let err = parse_advising_records(json!([record("CSE101"), bad])).unwrap_err();
+
+        match err {
+            TimetableError::UnexpectedPortalResponse { field, message } => {
+                assert_eq!(field, "courses[1].SectionName");
+                assert!(message.contains("MAT101"));
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn skips_dropped_courses() {
+        let mut dropped = record("MAT101");
+        dropped["DropStatus"] = json!("Yes");
+
+        let courses = parse_courses(json!([record("CSE101"), dropped])).unwrap();
+
+        assert_eq!(courses.len(), 1);
+        assert_eq!(courses[0].course_code, "CSE101");
+    }
+
+    #[test]
+    fn skips_unparsable_time_slot() {
+        let mut tba = record("MAT101");
+        tba["TimeSlotName"] = json!("TBA");
+
+        let courses = parse_courses(json!([record("CSE101"), tba])).unwrap();
+
+        assert_eq!(courses.len(), 2);
+        assert_eq!(courses[0].periods.len(), 2);
+        assert_eq!(courses[1].course_code, "MAT101");
+        assert!(courses[1].periods.is_empty());
+    }
+
+    #[test]
+    fn marks_lab_periods() {
+        let mut lab = record("CSE101");
+        lab["CourseTitle"] = json!("Structured Programming Lab");
+        lab["TimeSlotName"] = json!("R 10:10AM-12:10PM");
+
+        let courses = parse_courses(json!([record("CSE101"), lab])).unwrap();
+
+        let labs = courses[0]
+            .periods
+            .iter()
+            .map(|period| period.lab)
+            .collect::<Vec<_>>();
+        assert_eq!(labs, vec![false, false, true]);
+    }
+}
@@ -0,0 +1,337 @@
+use std::fmt::Display;
+
+use crate::{courses::Course, periods::Period};
+
+/// A difference between two snapshots of the same timetable, as found by [`diff`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    CourseAdded(Course),
+    CourseRemoved(Course),
+    SectionChanged {
+        course_code: String,
+        from: u8,
+        to: u8,
+    },
+    LecturerChanged {
+        course_code: String,
+        from: String,
+        to: String,
+    },
+    PeriodAdded {
+        course_code: String,
+        period: Period,
+    },
+    PeriodRemoved {
+        course_code: String,
+        period: Period,
+    },
+    /// A period now takes place on another day or at another time, and maybe in another room
+    PeriodMoved {
+        course_code: String,
+        from: Period,
+        to: Period,
+    },
+    /// A period keeps its time but takes place in another room
+    RoomChanged {
+        course_code: String,
+        period: Period,
+        from: String,
+    },
+}
+
+impl Change {
+    pub fn course_code(&self) -> &str {
+        match self {
+            Change::CourseAdded(course) | Change::CourseRemoved(course) => &course.course_code,
+            Change::SectionChanged { course_code, .. }
+            | Change::LecturerChanged { course_code, .. }
+            | Change::PeriodAdded { course_code, .. }
+            | Change::PeriodRemoved { course_code, .. }
+            | Change::PeriodMoved { course_code, .. }
+            | Change::RoomChanged { course_code, .. } => course_code,
+        }
+    }
+}
+
+struct Slot<'a>(&'a Period);
+
+impl Display for Slot<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}–{}",
+            self.0.day, self.0.start_time, self.0.end_time
+        )
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::CourseAdded(course) => write!(
+                f,
+                "Added {} ({}) with {}",
+                course.course_code, course.section, course.lecturer
+            ),
+            Change::CourseRemoved(course) => {
+                write!(f, "Removed {} ({})", course.course_code, course.section)
+            }
+            Change::SectionChanged {
+                course_code,
+                from,
+                to,
+            } => write!(f, "{}: section {} → {}", course_code, from, to),
+            Change::LecturerChanged {
+                course_code,
+                from,
+                to,
+            } => write!(f, "{}: lecturer {} → {}", course_code, from, to),
+            Change::PeriodAdded {
+                course_code,
+                period,
+            } => write!(
+                f,
+                "{}: new class on {} @ {}",
+                course_code,
+                Slot(period),
+                period.room
+            ),
+            Change::PeriodRemoved {
+                course_code,
+                period,
+            } => write!(
+                f,
+                "{}: no more class on {} @ {}",
+                course_code,
+                Slot(period),
+                period.room
+            ),
+            Change::PeriodMoved {
+                course_code,
+                from,
+                to,
+            } => write!(
+                f,
+                "{}: {} @ {} moved to {} @ {}",
+                course_code,
+                Slot(from),
+                from.room,
+                Slot(to),
+                to.room
+            ),
+            Change::RoomChanged {
+                course_code,
+                period,
+                from,
+            } => write!(
+                f,
+                "{}: {} moved from {} to {}",
+                course_code,
+                Slot(period),
+                from,
+                period.room
+            ),
+        }
+    }
+}
+
+fn same_slot(a: &Period, b: &Period) -> bool {
+    a.day == b.day && a.start_time == b.start_time && a.end_time == b.end_time
+}
+
+/// Takes the first period out of `periods` that `matches`
+fn take(periods: &mut Vec<Period>, matches: impl Fn(&Period) -> bool) -> Option<Period> {
+    let index = periods.iter().position(matches)?;
+    Some(periods.remove(index))
+}
+
+fn take_course(courses: &mut Vec<Course>, course_code: &str) -> Option<Course> {
+    let index = courses.iter().position(|c| c.course_code == course_code)?;
+    Some(courses.remove(index))
+}
+
+fn diff_periods(course_code: &str, old: &[Period], new: &[Period], changes: &mut Vec<Change>) {
+    let mut removed = old.to_vec();
+    let mut added = Vec::new();
+
+    for period in new {
+        match take(&mut removed, |old| same_slot(old, period)) {
+            Some(old) if old.room != period.room => changes.push(Change::RoomChanged {
+                course_code: course_code.to_string(),
+                period: period.clone(),
+                from: old.room,
+            }),
+            Some(_) => {}
+            None => added.push(period.clone()),
+        }
+    }
+
+    // whatever is left over on both sides is paired up as moves, preferring the same day
+    let mut moved = Vec::new();
+    added.retain(
+        |period| match take(&mut removed, |old| old.day == period.day) {
+            Some(old) => {
+                moved.push((old, period.clone()));
+                false
+            }
+            None => true,
+        },
+    );
+    let pairs = added.len().min(removed.len());
+    moved.extend(removed.drain(..pairs).zip(added.drain(..pairs)));
+
+    changes.extend(moved.into_iter().map(|(from, to)| Change::PeriodMoved {
+        course_code: course_code.to_string(),
+        from,
+        to,
+    }));
+    changes.extend(removed.into_iter().map(|period| Change::PeriodRemoved {
+        course_code: course_code.to_string(),
+        period,
+    }));
+    changes.extend(added.into_iter().map(|period| Change::PeriodAdded {
+        course_code: course_code.to_string(),
+        period,
+    }));
+}
+
+/// Lists what changed from the `old` courses to the `new` ones. Courses are
+/// matched by course code, so a section swap is reported as a change of the
+/// course rather than a removal and an addition.
+pub fn diff(old: &[Course], new: &[Course]) -> Vec<Change> {
+    let mut removed = old.to_vec();
+    let mut changes = Vec::new();
+
+    for course in new {
+        let Some(old) = take_course(&mut removed, &course.course_code) else {
+            changes.push(Change::CourseAdded(course.clone()));
+            continue;
+        };
+
+        if old.section != course.section {
+            changes.push(Change::SectionChanged {
+                course_code: course.course_code.clone(),
+                from: old.section,
+                to: course.section,
+            });
+        }
+        if old.lecturer != course.lecturer {
+            changes.push(Change::LecturerChanged {
+                course_code: course.course_code.clone(),
+                from: old.lecturer,
+                to: course.lecturer.clone(),
+            });
+        }
+        diff_periods(
+            &course.course_code,
+            &old.periods,
+            &course.periods,
+            &mut changes,
+        );
+    }
+
+    changes.extend(removed.into_iter().map(Change::CourseRemoved));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::periods::Weekday;
+    use crate::test_utils::period;
+
+    fn course(code: &str, section: u8, lecturer: &str, periods: Vec<Period>) -> Course {
+        Course {
+            course_code: code.to_string(),
+            section,
+            lecturer: lecturer.to_string(),
+            periods,
+        }
+    }
+
+    fn before() -> Vec<Course> {
+        vec![
+            course(
+                "CSE101",
+                2,
+                "Jane Doe",
+                vec![
+                    period(Weekday::Monday, (8, 30), (10, 0), "AB3-302"),
+                    period(Weekday::Wednesday, (8, 30), (10, 0), "AB3-302"),
+                ],
+            ),
+            course(
+                "MAT101",
+                5,
+                "John Smith",
+                vec![period(Weekday::Sunday, (13, 30), (15, 0), "FUB-201")],
+            ),
+            course("ENG101", 1, "Ann Lee", vec![]),
+        ]
+    }
+
+    #[test]
+    fn no_changes() {
+        assert_eq!(diff(&before(), &before()), vec![]);
+    }
+
+    #[test]
+    fn reports_every_kind_of_change() {
+        let after = vec![
+            course(
+                "CSE101",
+                2,
+                "Jane Doe",
+                vec![
+                    period(Weekday::Monday, (8, 30), (10, 0), "AB1-201"),
+                    period(Weekday::Wednesday, (10, 10), (11, 40), "AB3-302"),
+                    period(Weekday::Thursday, (10, 10), (12, 10), "630"),
+                ],
+            ),
+            course(
+                "MAT101",
+                6,
+                "Rita Roy",
+                vec![period(Weekday::Sunday, (13, 30), (15, 0), "FUB-201")],
+            ),
+            course("PHY101", 1, "Sam Sen", vec![]),
+        ];
+
+        let changes = diff(&before(), &after);
+        assert_eq!(
+            changes,
+            vec![
+                Change::RoomChanged {
+                    course_code: "CSE101".to_string(),
+                    period: period(Weekday::Monday, (8, 30), (10, 0), "AB1-201"),
+                    from: "AB3-302".to_string(),
+                },
+                Change::PeriodMoved {
+                    course_code: "CSE101".to_string(),
+                    from: period(Weekday::Wednesday, (8, 30), (10, 0), "AB3-302"),
+                    to: period(Weekday::Wednesday, (10, 10), (11, 40), "AB3-302"),
+                },
+                Change::PeriodAdded {
+                    course_code: "CSE101".to_string(),
+                    period: period(Weekday::Thursday, (10, 10), (12, 10), "630"),
+                },
+                Change::SectionChanged {
+                    course_code: "MAT101".to_string(),
+                    from: 5,
+                    to: 6,
+                },
+                Change::LecturerChanged {
+                    course_code: "MAT101".to_string(),
+                    from: "John Smith".to_string(),
+                    to: "Rita Roy".to_string(),
+                },
+                Change::CourseAdded(course("PHY101", 1, "Sam Sen", vec![])),
+                Change::CourseRemoved(course("ENG101", 1, "Ann Lee", vec![])),
+            ]
+        );
+        assert_eq!(
+            changes[1].to_string(),
+            "CSE101: Wednesday 08:30AM–10:00AM @ AB3-302 moved to Wednesday 10:10AM–11:40AM @ AB3-302"
+        );
+    }
+}
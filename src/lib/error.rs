@@ -0,0 +1,112 @@
+use std::{error::Error, fmt::Display};
+
+use reqwest::StatusCode;
+
+/// Errors returned by the library
+#[derive(Debug)]
+pub enum TimetableError {
+    /// The portal rejected the login, carries the message shown by the portal
+    InvalidCredentials(String),
+    /// The portal session is missing, expired or was never logged in
+    SessionExpired,
+    /// The portal could not be reached or failed to respond
+    PortalUnavailable(reqwest::Error),
+    /// The configured portal URL can't be sent in request headers, carries the URL
+    InvalidPortalUrl(String),
+    /// A portal response is missing a field or has an unexpected shape
+    UnexpectedPortalResponse { field: String, message: String },
+    /// A time slot could not be understood, carries the offending input
+    InvalidTimeSlot(String),
+    /// A time slot does not follow the expected grammar
+    TimeSlotSyntax {
+        input: String,
+        /// Character offset into `input` where parsing failed
+        position: usize,
+        expected: String,
+    },
+    /// A line of a holiday list could not be understood
+    InvalidHoliday { line: usize, message: String },
+    /// A line of a schedule change list could not be understood
+    InvalidOverride { line: usize, message: String },
+    /// A schedule change doesn't match any class, carries the change as written
+    UnmatchedOverride(String),
+    /// An entry of an exam list could not be understood. `entry` is the line of a
+    /// CSV list or the position in a JSON list, starting from 1, or 0 for the whole list.
+    InvalidExam { entry: usize, message: String },
+    /// iCalendar text is malformed or breaks RFC 5545, `line` starts from 1
+    InvalidCalendar { line: usize, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, TimetableError>;
+
+impl TimetableError {
+    pub fn unexpected(field: impl Into<String>, message: impl Into<String>) -> Self {
+        TimetableError::UnexpectedPortalResponse {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for TimetableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimetableError::InvalidCredentials(msg) => write!(f, "{}", msg),
+            TimetableError::SessionExpired => write!(f, "Portal session expired"),
+            TimetableError::PortalUnavailable(e) => write!(f, "Portal unavailable: {}", e),
+            TimetableError::InvalidPortalUrl(url) => write!(f, "Invalid portal URL: {}", url),
+            TimetableError::UnexpectedPortalResponse { field, message } => {
+                write!(f, "Unexpected portal response for {}: {}", field, message)
+            }
+            TimetableError::InvalidTimeSlot(slot) => write!(f, "Invalid time slot: {}", slot),
+            TimetableError::TimeSlotSyntax {
+                input,
+                position,
+                expected,
+            } => write!(
+                f,
+                "Invalid time slot \"{}\": expected {} at position {}",
+                input, expected, position
+            ),
+            TimetableError::InvalidHoliday { line, message } => {
+                write!(f, "Invalid holiday on line {}: {}", line, message)
+            }
+            TimetableError::InvalidOverride { line, message } => {
+                write!(f, "Invalid schedule change on line {}: {}", line, message)
+            }
+            TimetableError::UnmatchedOverride(change) => {
+                write!(f, "Schedule change \"{}\" doesn't match any class", change)
+            }
+            TimetableError::InvalidExam { entry: 0, message } => {
+                write!(f, "Invalid exam list: {}", message)
+            }
+            TimetableError::InvalidExam { entry, message } => {
+                write!(f, "Invalid exam in entry {}: {}", entry, message)
+            }
+            TimetableError::InvalidCalendar { line, message } => {
+                write!(f, "Invalid calendar on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for TimetableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TimetableError::PortalUnavailable(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for TimetableError {
+    fn from(e: reqwest::Error) -> Self {
+        match e.status() {
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+                TimetableError::SessionExpired
+            }
+            _ if e.is_decode() => TimetableError::unexpected("body", e.to_string()),
+            _ => TimetableError::PortalUnavailable(e),
+        }
+    }
+}
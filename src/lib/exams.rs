@@ -0,0 +1,235 @@
+use std::fmt::Display;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{Result, TimetableError},
+    periods::Time,
+    time_slot,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExamKind {
+    Midterm,
+    Final,
+}
+
+impl ExamKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "mid" | "midterm" => Some(ExamKind::Midterm),
+            "final" => Some(ExamKind::Final),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ExamKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExamKind::Midterm => write!(f, "Midterm"),
+            ExamKind::Final => write!(f, "Final"),
+        }
+    }
+}
+
+/// A single sitting of a course's exam
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Exam {
+    pub course_code: String,
+    pub kind: ExamKind,
+    pub date: NaiveDate,
+    pub start_time: Time,
+    pub end_time: Time,
+    pub room: Option<String>,
+}
+
+/// An exam as written in a JSON exam list, with 12-hr times such as "9:00AM"
+#[derive(Debug, Deserialize)]
+struct ExamRecord {
+    course_code: String,
+    kind: String,
+    date: NaiveDate,
+    start_time: String,
+    end_time: String,
+    #[serde(default)]
+    room: Option<String>,
+}
+
+impl ExamRecord {
+    fn into_exam(self, entry: usize) -> Result<Exam> {
+        let invalid = |message: String| TimetableError::InvalidExam { entry, message };
+
+        let kind = ExamKind::parse(&self.kind).ok_or_else(|| {
+            invalid(format!(
+                "expected \"midterm\" or \"final\", got \"{}\"",
+                self.kind
+            ))
+        })?;
+        let start_time =
+            time_slot::parse_time(&self.start_time).map_err(|e| invalid(e.to_string()))?;
+        let end_time = time_slot::parse_time(&self.end_time).map_err(|e| invalid(e.to_string()))?;
+        if (end_time.hours, end_time.minutes) <= (start_time.hours, start_time.minutes) {
+            return Err(invalid(format!("{} is not after {}", end_time, start_time)));
+        }
+
+        Ok(Exam {
+            course_code: self.course_code.trim().to_string(),
+            kind,
+            date: self.date,
+            start_time,
+            end_time,
+            room: self
+                .room
+                .map(|room| room.trim().to_string())
+                .filter(|room| !room.is_empty()),
+        })
+    }
+}
+
+/// Parse one exam per line in the form "<course>,<kind>,<date>,<start>,<end>[,<room>]",
+/// e.g. "CSE101,final,2024-12-21,9:00AM,11:00AM,AB3-302". The kind is "midterm" or
+/// "final". An optional "course,kind,..." header, blank lines and lines starting
+/// with '#' are ignored.
+pub fn parse_exams_csv(text: &str) -> Result<Vec<Exam>> {
+    let mut exams = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if exams.is_empty() && line.to_ascii_lowercase().starts_with("course") {
+            continue;
+        }
+
+        let invalid = |message: String| TimetableError::InvalidExam {
+            entry: index + 1,
+            message,
+        };
+
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        let [course_code, kind, date, start_time, end_time, rest @ ..] = fields.as_slice() else {
+            return Err(invalid(
+                "expected \"<course>,<kind>,<date>,<start>,<end>[,<room>]\"".to_string(),
+            ));
+        };
+        if rest.len() > 1 {
+            return Err(invalid("too many fields".to_string()));
+        }
+
+        let record = ExamRecord {
+            course_code: course_code.to_string(),
+            kind: kind.to_string(),
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|e| invalid(format!("\"{}\": {}", date, e)))?,
+            start_time: start_time.to_string(),
+            end_time: end_time.to_string(),
+            room: rest.first().map(|room| room.to_string()),
+        };
+        exams.push(record.into_exam(index + 1)?);
+    }
+
+    Ok(exams)
+}
+
+/// Parse a JSON array of exams with the fields `course_code`, `kind`, `date`,
+/// `start_time`, `end_time` and optionally `room`, using the same values as
+/// [`parse_exams_csv`]
+pub fn parse_exams_json(text: &str) -> Result<Vec<Exam>> {
+    let records: Vec<serde_json::Value> =
+        serde_json::from_str(text).map_err(|e| TimetableError::InvalidExam {
+            entry: 0,
+            message: e.to_string(),
+        })?;
+
+    records
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| {
+            serde_json::from_value::<ExamRecord>(record)
+                .map_err(|e| TimetableError::InvalidExam {
+                    entry: index + 1,
+                    message: e.to_string(),
+                })?
+                .into_exam(index + 1)
+        })
+        .collect()
+}
+
+/// Parse an exam list in either format, JSON if it starts with '['
+pub fn parse_exams(text: &str) -> Result<Vec<Exam>> {
+    if text.trim_start().starts_with('[') {
+        parse_exams_json(text)
+    } else {
+        parse_exams_csv(text)
+    }
+}
+
+/// Day the final exam period begins, if any final exam is known
+pub fn finals_start(exams: &[Exam]) -> Option<NaiveDate> {
+    exams
+        .iter()
+        .filter(|exam| exam.kind == ExamKind::Final)
+        .map(|exam| exam.date)
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::date;
+
+    fn expected() -> Vec<Exam> {
+        vec![
+            Exam {
+                course_code: "CSE101".to_string(),
+                kind: ExamKind::Midterm,
+                date: date(2024, 10, 27),
+                start_time: Time::new(8, 30),
+                end_time: Time::new(10, 0),
+                room: None,
+            },
+            Exam {
+                course_code: "CSE101".to_string(),
+                kind: ExamKind::Final,
+                date: date(2024, 12, 21),
+                start_time: Time::new(9, 0),
+                end_time: Time::new(11, 0),
+                room: Some("AB3-302".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn parses_csv_and_json() {
+        let csv = "course,kind,date,start,end,room\n\
+                   CSE101, mid, 2024-10-27, 8:30AM, 10:00AM\n\
+                   # finals\n\
+                   CSE101,Final,2024-12-21,9:00AM,11:00AM,AB3-302";
+        assert_eq!(parse_exams(csv).unwrap(), expected());
+
+        let json = r#"[
+            {"course_code": "CSE101", "kind": "midterm", "date": "2024-10-27", "start_time": "8:30AM", "end_time": "10:00AM"},
+            {"course_code": "CSE101", "kind": "final", "date": "2024-12-21", "start_time": "9:00 am", "end_time": "11:00 am", "room": "AB3-302"}
+        ]"#;
+        assert_eq!(parse_exams(json).unwrap(), expected());
+
+        assert_eq!(finals_start(&expected()), Some(date(2024, 12, 21)));
+    }
+
+    #[test]
+    fn reports_invalid_entry() {
+        let err = parse_exams(
+            "CSE101,final,2024-12-21,9:00AM,11:00AM\nCSE101,quiz,2024-12-21,9:00AM,11:00AM",
+        )
+        .unwrap_err();
+        assert!(matches!(err, TimetableError::InvalidExam { entry: 2, .. }));
+
+        let err = parse_exams(r#"[{"course_code": "CSE101", "kind": "final"}]"#).unwrap_err();
+        assert!(matches!(err, TimetableError::InvalidExam { entry: 1, .. }));
+    }
+}
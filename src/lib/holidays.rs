@@ -0,0 +1,188 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::{Result, TimetableError};
+
+/// Fixed-date public holidays bundled with the library, in the format read by [`parse_holidays`]
+pub const BUNDLED_HOLIDAYS: &str = include_str!("holidays.txt");
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub enum HolidayDate {
+    /// Consecutive days from `start` to `end`, inclusive
+    Range { start: NaiveDate, end: NaiveDate },
+    /// The same day every year
+    Yearly { month: u32, day: u32 },
+}
+
+/// A day or range of days without classes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Holiday {
+    pub name: String,
+    pub date: HolidayDate,
+}
+
+impl Holiday {
+    /// Days of the holiday between `from` and `to`, inclusive
+    pub fn days_between(&self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        match self.date {
+            HolidayDate::Range { start, end } => start
+                .max(from)
+                .iter_days()
+                .take_while(|day| *day <= end.min(to))
+                .collect(),
+            HolidayDate::Yearly { month, day } => (from.year()..=to.year())
+                .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+                .filter(|date| (from..=to).contains(date))
+                .collect(),
+        }
+    }
+}
+
+fn parse_date(date: &str, line: usize) -> Result<HolidayDate> {
+    let invalid = |message: String| TimetableError::InvalidHoliday { line, message };
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .map_err(|e| invalid(format!("\"{}\": {}", date.trim(), e)))
+    };
+
+    if let Some(month_day) = date.trim().strip_prefix("*-") {
+        // validate against a leap year so that Feb 29 is accepted
+        let date = parse(&format!("2000-{}", month_day))?;
+        return Ok(HolidayDate::Yearly {
+            month: date.month(),
+            day: date.day(),
+        });
+    }
+
+    let (start, end) = match date.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let date = parse(date)?;
+            (date, date)
+        }
+    };
+    if end < start {
+        return Err(invalid(format!("{} is before {}", end, start)));
+    }
+
+    Ok(HolidayDate::Range { start, end })
+}
+
+/// Parse one holiday per line in the form "<date>, <name>", where the date is
+/// "YYYY-MM-DD", a range "YYYY-MM-DD..YYYY-MM-DD", or "*-MM-DD" for every year.
+/// Blank lines and lines starting with '#' are ignored.
+pub fn parse_holidays(text: &str) -> Result<Vec<Holiday>> {
+    let mut holidays = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (date, name) = line
+            .split_once(',')
+            .ok_or_else(|| TimetableError::InvalidHoliday {
+                line: index + 1,
+                message: "expected \"<date>, <name>\"".to_string(),
+            })?;
+
+        holidays.push(Holiday {
+            name: name.trim().to_string(),
+            date: parse_date(date, index + 1)?,
+        });
+    }
+
+    Ok(holidays)
+}
+
+pub fn bundled_holidays() -> Vec<Holiday> {
+    parse_holidays(BUNDLED_HOLIDAYS).expect("bundled holidays should be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::date;
+
+    #[test]
+    fn parses_holiday_lines() {
+        let holidays = parse_holidays(
+            "# closures\n\
+             2024-12-16, Victory Day\n\
+             \n\
+             2024-10-10..2024-10-14, Durga Puja\n\
+             *-02-21 , Language Martyrs' Day",
+        )
+        .unwrap();
+
+        assert_eq!(
+            holidays,
+            vec![
+                Holiday {
+                    name: "Victory Day".to_string(),
+                    date: HolidayDate::Range {
+                        start: date(2024, 12, 16),
+                        end: date(2024, 12, 16)
+                    },
+                },
+                Holiday {
+                    name: "Durga Puja".to_string(),
+                    date: HolidayDate::Range {
+                        start: date(2024, 10, 10),
+                        end: date(2024, 10, 14)
+                    },
+                },
+                Holiday {
+                    name: "Language Martyrs' Day".to_string(),
+                    date: HolidayDate::Yearly { month: 2, day: 21 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_line() {
+        let err = parse_holidays("2024-12-16, Victory Day\n2024-13-01, Nope").unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::InvalidHoliday { line: 2, .. }
+        ));
+
+        let err = parse_holidays("2024-12-16 Victory Day").unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::InvalidHoliday { line: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn expands_days_between() {
+        let puja = Holiday {
+            name: "Durga Puja".to_string(),
+            date: HolidayDate::Range {
+                start: date(2024, 10, 10),
+                end: date(2024, 10, 14),
+            },
+        };
+        assert_eq!(
+            puja.days_between(date(2024, 10, 12), date(2024, 12, 31)),
+            vec![date(2024, 10, 12), date(2024, 10, 13), date(2024, 10, 14)]
+        );
+
+        let victory_day = Holiday {
+            name: "Victory Day".to_string(),
+            date: HolidayDate::Yearly { month: 12, day: 16 },
+        };
+        assert_eq!(
+            victory_day.days_between(date(2024, 9, 1), date(2025, 12, 1)),
+            vec![date(2024, 12, 16)]
+        );
+    }
+
+    #[test]
+    fn bundled_holidays_parse() {
+        assert!(!bundled_holidays().is_empty());
+    }
+}
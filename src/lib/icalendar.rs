@@ -0,0 +1,879 @@
+//! Reading back iCalendar text: a parser for content lines and components, a
+//! validator for the parts of RFC 5545 that [`crate::calendar`] writes, and
+//! expansion of weekly recurrences into their instances.
+
+use std::collections::HashSet;
+
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, Utc};
+
+use crate::error::{Result, TimetableError};
+
+/// Longest content line allowed before it has to be folded, in octets
+const MAX_LINE_LENGTH: usize = 75;
+
+fn invalid(line: usize, message: impl Into<String>) -> TimetableError {
+    TimetableError::InvalidCalendar {
+        line,
+        message: message.into(),
+    }
+}
+
+/// An unfolded content line such as `DTSTART;TZID=Asia/Dhaka:20240902T083000`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+    /// Line of the calendar text the property starts on, starting from 1
+    pub line: usize,
+}
+
+impl Property {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A `BEGIN:<name>` ... `END:<name>` block
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+    /// Line of the `BEGIN`, starting from 1
+    pub line: usize,
+}
+
+impl Component {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn properties<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property> {
+        self.properties
+            .iter()
+            .filter(move |property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn components<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Component> {
+        self.components
+            .iter()
+            .filter(move |component| component.name.eq_ignore_ascii_case(name))
+    }
+
+    fn required(&self, name: &str) -> Result<&Property> {
+        let mut properties = self
+            .properties
+            .iter()
+            .filter(|property| property.name.eq_ignore_ascii_case(name));
+        let property = properties
+            .next()
+            .ok_or_else(|| invalid(self.line, format!("{} without {}", self.name, name)))?;
+        if let Some(duplicate) = properties.next() {
+            return Err(invalid(
+                duplicate.line,
+                format!("{} has more than one {}", self.name, name),
+            ));
+        }
+        Ok(property)
+    }
+}
+
+/// Splits a parameter list on `sep`, ignoring separators inside quotes
+fn split_unquoted(text: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&text[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn parse_property(content: &str, line: usize) -> Result<Property> {
+    // the value starts at the first colon outside of a quoted parameter value
+    let mut quoted = false;
+    let colon = content
+        .char_indices()
+        .find(|&(_, c)| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ':' && !quoted
+        })
+        .map(|(index, _)| index)
+        .ok_or_else(|| invalid(line, format!("expected ':' in \"{}\"", content)))?;
+
+    let mut head = split_unquoted(&content[..colon], ';').into_iter();
+    let name = head.next().unwrap_or_default();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(invalid(line, format!("invalid property name \"{}\"", name)));
+    }
+
+    let params = head
+        .map(|param| {
+            param
+                .split_once('=')
+                .map(|(name, value)| (name.to_string(), value.trim_matches('"').to_string()))
+                .ok_or_else(|| invalid(line, format!("expected '=' in parameter \"{}\"", param)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Property {
+        name: name.to_ascii_uppercase(),
+        params,
+        value: content[colon + 1..].to_string(),
+        line,
+    })
+}
+
+/// Parse iCalendar text into its top-level component, usually a `VCALENDAR`
+pub fn parse(ical: &str) -> Result<Component> {
+    // (content, line it starts on)
+    let mut lines: Vec<(String, usize)> = Vec::new();
+    for (index, line) in ical.split_inclusive('\n').enumerate() {
+        let line_number = index + 1;
+        let line = line
+            .strip_suffix("\r\n")
+            .ok_or_else(|| invalid(line_number, "lines must end with CRLF"))?;
+
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some((content, _))) => content.push_str(continuation),
+            (Some(_), None) => return Err(invalid(line_number, "folded line without a start")),
+            (None, _) if line.is_empty() => {}
+            (None, _) => lines.push((line.to_string(), line_number)),
+        }
+    }
+
+    let mut stack: Vec<Component> = Vec::new();
+    let mut root = None;
+    for (content, line) in lines {
+        let property = parse_property(&content, line)?;
+
+        match property.name.as_str() {
+            "BEGIN" => {
+                if root.is_some() {
+                    return Err(invalid(line, "content after the end of the calendar"));
+                }
+                stack.push(Component {
+                    name: property.value.to_ascii_uppercase(),
+                    properties: Vec::new(),
+                    components: Vec::new(),
+                    line,
+                });
+            }
+            "END" => {
+                let component = stack
+                    .pop()
+                    .filter(|component| component.name.eq_ignore_ascii_case(&property.value))
+                    .ok_or_else(|| invalid(line, format!("unexpected END:{}", property.value)))?;
+                match stack.last_mut() {
+                    Some(parent) => parent.components.push(component),
+                    None => root = Some(component),
+                }
+            }
+            _ => stack
+                .last_mut()
+                .ok_or_else(|| invalid(line, "property outside of a component"))?
+                .properties
+                .push(property),
+        }
+    }
+
+    if let Some(component) = stack.pop() {
+        return Err(invalid(
+            component.line,
+            format!("BEGIN:{} without END", component.name),
+        ));
+    }
+    root.ok_or_else(|| invalid(0, "empty calendar"))
+}
+
+/// A DATE or DATE-TIME value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateValue {
+    Date(NaiveDate),
+    /// Local time, in the time zone of the TZID parameter if there is one
+    Local(NaiveDateTime),
+    Utc(NaiveDateTime),
+}
+
+impl DateValue {
+    fn parse(value: &str, line: usize) -> Result<Self> {
+        let error = |e: chrono::ParseError| invalid(line, format!("\"{}\": {}", value, e));
+
+        if let Some(utc) = value.strip_suffix('Z') {
+            NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+                .map(DateValue::Utc)
+                .map_err(error)
+        } else if value.contains('T') {
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+                .map(DateValue::Local)
+                .map_err(error)
+        } else {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .map(DateValue::Date)
+                .map_err(error)
+        }
+    }
+
+    fn is_date(self) -> bool {
+        matches!(self, DateValue::Date(_))
+    }
+
+    /// The value as a local date and time, midnight for dates
+    fn local(self, offset: FixedOffset) -> NaiveDateTime {
+        match self {
+            DateValue::Date(date) => date.and_time(NaiveTime::MIN),
+            DateValue::Local(datetime) => datetime,
+            DateValue::Utc(datetime) => datetime + offset,
+        }
+    }
+}
+
+/// Values of a date property such as DTSTART or EXDATE, which may hold a list
+pub fn date_values(property: &Property) -> Result<Vec<DateValue>> {
+    let values = property
+        .value
+        .split(',')
+        .map(|value| DateValue::parse(value, property.line))
+        .collect::<Result<Vec<_>>>()?;
+
+    let declared_date = property
+        .param("VALUE")
+        .is_some_and(|value| value.eq_ignore_ascii_case("DATE"));
+    if values.iter().any(|value| value.is_date() != declared_date) {
+        return Err(invalid(
+            property.line,
+            format!("{} values must match its VALUE parameter", property.name),
+        ));
+    }
+    if property.param("TZID").is_some()
+        && values
+            .iter()
+            .any(|value| !matches!(value, DateValue::Local(_)))
+    {
+        return Err(invalid(
+            property.line,
+            format!("{} with a TZID must be a local date-time", property.name),
+        ));
+    }
+
+    Ok(values)
+}
+
+/// Seconds in a run of duration components like `1H30M`, which must come in
+/// the order of `units`
+fn duration_seconds(text: &str, units: &[(char, i64)]) -> Option<i64> {
+    let mut seconds = 0;
+    let mut units = units.iter();
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let (_, unit) = units.find(|(name, _)| *name == c)?;
+        seconds += number.parse::<i64>().ok()?.checked_mul(*unit)?;
+        number.clear();
+    }
+
+    number.is_empty().then_some(seconds)
+}
+
+/// Parse a DURATION property such as `PT1H30M` or `P1W`
+pub fn parse_duration(property: &Property) -> Result<Duration> {
+    let error = || {
+        invalid(
+            property.line,
+            format!("invalid duration \"{}\"", property.value),
+        )
+    };
+    let (sign, rest) = match property.value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (
+            1,
+            property.value.strip_prefix('+').unwrap_or(&property.value),
+        ),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(error)?;
+    let (days, time) = match rest.split_once('T') {
+        Some((_, "")) => return Err(error()),
+        Some((days, time)) => (days, time),
+        None if rest.is_empty() => return Err(error()),
+        None => (rest, ""),
+    };
+
+    let seconds = duration_seconds(days, &[('W', 7 * 24 * 60 * 60), ('D', 24 * 60 * 60)])
+        .zip(duration_seconds(
+            time,
+            &[('H', 60 * 60), ('M', 60), ('S', 1)],
+        ))
+        .ok_or_else(error)?;
+    Ok(Duration::seconds(sign * (seconds.0 + seconds.1)))
+}
+
+/// A parsed RRULE value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    pub freq: String,
+    pub interval: u32,
+    pub by_day: Vec<chrono::Weekday>,
+    pub until: Option<DateValue>,
+    pub count: Option<u32>,
+}
+
+fn parse_weekday(day: &str) -> Option<chrono::Weekday> {
+    match day {
+        "SU" => Some(chrono::Weekday::Sun),
+        "MO" => Some(chrono::Weekday::Mon),
+        "TU" => Some(chrono::Weekday::Tue),
+        "WE" => Some(chrono::Weekday::Wed),
+        "TH" => Some(chrono::Weekday::Thu),
+        "FR" => Some(chrono::Weekday::Fri),
+        "SA" => Some(chrono::Weekday::Sat),
+        _ => None,
+    }
+}
+
+/// Parse an RRULE property, checking it against the event's DTSTART
+pub fn parse_recurrence(rrule: &Property, dtstart: &Property) -> Result<Recurrence> {
+    let line = rrule.line;
+    let start = date_values(dtstart)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid(dtstart.line, "empty DTSTART"))?;
+    let floating = matches!(start, DateValue::Local(_)) && dtstart.param("TZID").is_none();
+    if !rrule.params.is_empty() {
+        return Err(invalid(
+            line,
+            "RRULE rule parts must be in its value, not parameters",
+        ));
+    }
+
+    let mut freq = None;
+    let mut recurrence = Recurrence {
+        freq: String::new(),
+        interval: 1,
+        by_day: Vec::new(),
+        until: None,
+        count: None,
+    };
+    let mut seen = HashSet::new();
+
+    for part in rrule.value.split(';') {
+        let (name, value) = part
+            .split_once('=')
+            .ok_or_else(|| invalid(line, format!("expected '=' in rule part \"{}\"", part)))?;
+        if !seen.insert(name) {
+            return Err(invalid(line, format!("rule part {} is repeated", name)));
+        }
+        let number = |value: &str| {
+            value
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| invalid(line, format!("{} must be a positive number", name)))
+        };
+
+        match name {
+            "FREQ" => freq = Some(value.to_string()),
+            "INTERVAL" => recurrence.interval = number(value)?,
+            "COUNT" => recurrence.count = Some(number(value)?),
+            "UNTIL" => recurrence.until = Some(DateValue::parse(value, line)?),
+            "BYDAY" => {
+                recurrence.by_day = value
+                    .split(',')
+                    .map(|day| {
+                        parse_weekday(day)
+                            .ok_or_else(|| invalid(line, format!("invalid BYDAY \"{}\"", day)))
+                    })
+                    .collect::<Result<_>>()?
+            }
+            "WKST" if parse_weekday(value).is_some() => {}
+            _ => return Err(invalid(line, format!("unsupported rule part \"{}\"", part))),
+        }
+    }
+
+    recurrence.freq = freq.ok_or_else(|| invalid(line, "RRULE without FREQ"))?;
+    if ![
+        "SECONDLY", "MINUTELY", "HOURLY", "DAILY", "WEEKLY", "MONTHLY", "YEARLY",
+    ]
+    .contains(&recurrence.freq.as_str())
+    {
+        return Err(invalid(
+            line,
+            format!("invalid FREQ \"{}\"", recurrence.freq),
+        ));
+    }
+    if recurrence.until.is_some() && recurrence.count.is_some() {
+        return Err(invalid(line, "RRULE can't have both UNTIL and COUNT"));
+    }
+    match (start, recurrence.until) {
+        (_, None) => {}
+        (DateValue::Date(_), Some(DateValue::Date(_))) => {}
+        (DateValue::Date(_), Some(_)) => {
+            return Err(invalid(line, "UNTIL must be a date when DTSTART is a date"))
+        }
+        (_, Some(DateValue::Utc(_))) => {}
+        (_, Some(DateValue::Local(_))) if floating => {}
+        (_, Some(_)) => {
+            return Err(invalid(
+                line,
+                "UNTIL must be a UTC date-time when DTSTART has a time zone",
+            ))
+        }
+    }
+
+    Ok(recurrence)
+}
+
+/// Offset of a VTIMEZONE with a fixed offset, as written by [`crate::calendar`]
+fn timezone_offset(calendar: &Component, tzid: &str) -> Result<FixedOffset> {
+    let timezone = calendar
+        .components("VTIMEZONE")
+        .find(|timezone| timezone.property("TZID").is_some_and(|id| id.value == tzid))
+        .ok_or_else(|| invalid(calendar.line, format!("unknown time zone \"{}\"", tzid)))?;
+    let observance = timezone
+        .components
+        .first()
+        .ok_or_else(|| invalid(timezone.line, "VTIMEZONE without STANDARD or DAYLIGHT"))?;
+    let offset = observance.required("TZOFFSETTO")?;
+
+    let (sign, digits) = offset.value.split_at(1.min(offset.value.len()));
+    let seconds = match (digits.get(..2), digits.get(2..4)) {
+        (Some(hours), Some(minutes)) if digits.len() == 4 => hours
+            .parse::<i32>()
+            .ok()
+            .zip(minutes.parse::<i32>().ok())
+            .map(|(hours, minutes)| hours * 3600 + minutes * 60),
+        _ => None,
+    };
+    match (sign, seconds) {
+        ("+", Some(seconds)) => FixedOffset::east_opt(seconds),
+        ("-", Some(seconds)) => FixedOffset::west_opt(seconds),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(offset.line, format!("invalid offset \"{}\"", offset.value)))
+}
+
+fn event_offset(calendar: &Component, dtstart: &Property) -> Result<FixedOffset> {
+    match dtstart.param("TZID") {
+        Some(tzid) => timezone_offset(calendar, tzid),
+        None => Ok(Utc.fix()),
+    }
+}
+
+/// Start of every instance of an event, in the event's own time zone. Weekly
+/// RRULEs are expanded, RDATEs added and EXDATEs removed.
+pub fn instances(calendar: &Component, event: &Component) -> Result<Vec<NaiveDateTime>> {
+    let dtstart = event.required("DTSTART")?;
+    let offset = event_offset(calendar, dtstart)?;
+    let start = date_values(dtstart)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid(dtstart.line, "empty DTSTART"))?;
+    let first = start.local(offset);
+
+    let mut instances = vec![first];
+
+    if let Some(rrule) = event.property("RRULE") {
+        let recurrence = parse_recurrence(rrule, dtstart)?;
+        if recurrence.freq != "WEEKLY" {
+            return Err(invalid(
+                rrule.line,
+                "only weekly recurrences can be expanded",
+            ));
+        }
+        if recurrence.until.is_none() && recurrence.count.is_none() {
+            return Err(invalid(
+                rrule.line,
+                "unbounded recurrences can't be expanded",
+            ));
+        }
+        let until = recurrence.until.map(|until| until.local(offset));
+        let days = if recurrence.by_day.is_empty() {
+            vec![first.weekday()]
+        } else {
+            recurrence.by_day.clone()
+        };
+
+        instances.clear();
+        let week_start =
+            first.date() - Duration::days(first.weekday().num_days_from_monday() as i64);
+        'weeks: for week in (0..).step_by(recurrence.interval as usize) {
+            let mut week_days = days
+                .iter()
+                .map(|day| {
+                    (week_start
+                        + Duration::weeks(week)
+                        + Duration::days(day.num_days_from_monday() as i64))
+                    .and_time(first.time())
+                })
+                .collect::<Vec<_>>();
+            week_days.sort();
+
+            for instance in week_days {
+                if instance < first {
+                    continue;
+                }
+                if until.is_some_and(|until| instance > until)
+                    || recurrence
+                        .count
+                        .is_some_and(|count| instances.len() >= count as usize)
+                {
+                    break 'weeks;
+                }
+                instances.push(instance);
+            }
+        }
+    }
+
+    for rdate in event.properties("RDATE") {
+        instances.extend(
+            date_values(rdate)?
+                .into_iter()
+                .map(|value| value.local(offset)),
+        );
+    }
+    let mut excluded = HashSet::new();
+    for exdate in event.properties("EXDATE") {
+        excluded.extend(
+            date_values(exdate)?
+                .into_iter()
+                .map(|value| value.local(offset)),
+        );
+    }
+
+    instances.retain(|instance| !excluded.contains(instance));
+    instances.sort();
+    instances.dedup();
+    Ok(instances)
+}
+
+fn validate_event(
+    calendar: &Component,
+    event: &Component,
+    timezones: &HashSet<&str>,
+) -> Result<()> {
+    event.required("UID")?;
+    let dtstamp = event.required("DTSTAMP")?;
+    if !matches!(
+        DateValue::parse(&dtstamp.value, dtstamp.line)?,
+        DateValue::Utc(_)
+    ) {
+        return Err(invalid(dtstamp.line, "DTSTAMP must be a UTC date-time"));
+    }
+
+    for property in &event.properties {
+        if let Some(tzid) = property.param("TZID") {
+            if !timezones.contains(tzid) {
+                return Err(invalid(
+                    property.line,
+                    format!("unknown time zone \"{}\"", tzid),
+                ));
+            }
+        }
+    }
+
+    let dtstart = event.required("DTSTART")?;
+    let start = match date_values(dtstart)?.as_slice() {
+        [start] => *start,
+        _ => return Err(invalid(dtstart.line, "DTSTART must be a single value")),
+    };
+    let offset = event_offset(calendar, dtstart)?;
+
+    if let Some(dtend) = event.property("DTEND") {
+        match date_values(dtend)?.as_slice() {
+            [end] if end.is_date() == start.is_date() => {
+                if end.local(offset) <= start.local(offset) {
+                    return Err(invalid(dtend.line, "DTEND must be after DTSTART"));
+                }
+            }
+            _ => {
+                return Err(invalid(
+                    dtend.line,
+                    "DTEND must be a single value like DTSTART",
+                ))
+            }
+        }
+    }
+    if let Some(duration) = event.property("DURATION") {
+        if event.property("DTEND").is_some() {
+            return Err(invalid(
+                duration.line,
+                "an event can't have both DTEND and DURATION",
+            ));
+        }
+        if parse_duration(duration)? < Duration::zero() {
+            return Err(invalid(duration.line, "DURATION can't be negative"));
+        }
+    }
+
+    for name in ["RDATE", "EXDATE", "RECURRENCE-ID"] {
+        for property in event.properties(name) {
+            if date_values(property)?
+                .iter()
+                .any(|value| value.is_date() != start.is_date())
+            {
+                return Err(invalid(
+                    property.line,
+                    format!("{} must be of the same type as DTSTART", name),
+                ));
+            }
+        }
+    }
+    if let Some(rrule) = event.property("RRULE") {
+        parse_recurrence(rrule, dtstart)?;
+    }
+
+    for alarm in event.components("VALARM") {
+        let action = alarm.required("ACTION")?;
+        alarm.required("TRIGGER")?;
+        if action.value == "DISPLAY" {
+            alarm.required("DESCRIPTION")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Refolds CRLF-terminated iCalendar text so that no line is longer than 75
+/// octets including the space a continuation starts with, which the ics crate
+/// writes after 75 octets of content. Characters are never split.
+pub fn fold(ical: &str) -> String {
+    let mut folded = String::with_capacity(ical.len() + ical.len() / 64);
+    let mut content = String::new();
+    let mut flush = |content: &mut String| {
+        let mut limit = MAX_LINE_LENGTH;
+        let mut length = 0;
+        for c in content.chars() {
+            if length + c.len_utf8() > limit {
+                folded.push_str("\r\n ");
+                // the space counts toward the continuation's length
+                limit = MAX_LINE_LENGTH - 1;
+                length = 0;
+            }
+            folded.push(c);
+            length += c.len_utf8();
+        }
+        folded.push_str("\r\n");
+        content.clear();
+    };
+
+    for (index, line) in ical.split_terminator("\r\n").enumerate() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(continuation) if index > 0 => content.push_str(continuation),
+            _ => {
+                if index > 0 {
+                    flush(&mut content);
+                }
+                content.push_str(line);
+            }
+        }
+    }
+    if !ical.is_empty() {
+        flush(&mut content);
+    }
+    folded
+}
+
+/// Checks that `ical` is a calendar that follows RFC 5545, as far as the
+/// properties written by [`crate::calendar`] go
+pub fn validate(ical: &str) -> Result<()> {
+    for (index, line) in ical.split("\r\n").enumerate() {
+        if line.len() > MAX_LINE_LENGTH {
+            return Err(invalid(
+                index + 1,
+                "line longer than 75 octets is not folded",
+            ));
+        }
+    }
+
+    let calendar = parse(ical)?;
+    if calendar.name != "VCALENDAR" {
+        return Err(invalid(calendar.line, "expected VCALENDAR"));
+    }
+    let version = calendar.required("VERSION")?;
+    if version.value != "2.0" {
+        return Err(invalid(version.line, "VERSION must be 2.0"));
+    }
+    calendar.required("PRODID")?;
+
+    let mut timezones = HashSet::new();
+    for timezone in calendar.components("VTIMEZONE") {
+        timezones.insert(timezone.required("TZID")?.value.as_str());
+        timezone_offset(&calendar, &timezone.required("TZID")?.value)?;
+    }
+
+    let mut ids = HashSet::new();
+    for event in calendar.components("VEVENT") {
+        validate_event(&calendar, event, &timezones)?;
+
+        let uid = &event.required("UID")?.value;
+        let recurrence_id = event.property("RECURRENCE-ID").map(|id| id.value.as_str());
+        if !ids.insert((uid.as_str(), recurrence_id)) {
+            return Err(invalid(
+                event.line,
+                format!("UID \"{}\" is used twice", uid),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar(event: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Test//EN\r\n\
+             BEGIN:VTIMEZONE\r\nTZID:Asia/Dhaka\r\n\
+             BEGIN:STANDARD\r\nDTSTART:19700101T000000\r\nTZOFFSETFROM:+0600\r\nTZOFFSETTO:+0600\r\nEND:STANDARD\r\n\
+             END:VTIMEZONE\r\n\
+             BEGIN:VEVENT\r\nUID:1@test\r\nDTSTAMP:20240801T000000Z\r\n\
+             DTSTART;TZID=Asia/Dhaka:20240902T083000\r\nDTEND;TZID=Asia/Dhaka:20240902T100000\r\n\
+             {}END:VEVENT\r\nEND:VCALENDAR\r\n",
+            event
+        )
+    }
+
+    fn datetime(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn folds_to_75_octets_with_the_space() {
+        let description = format!("DESCRIPTION:{}", "é".repeat(100));
+        let ical = format!(
+            "BEGIN:VEVENT\r\n{}\r\n  continued\r\nEND:VEVENT\r\n",
+            description
+        );
+
+        let folded = fold(&ical);
+        assert!(folded
+            .split_terminator("\r\n")
+            .all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert_eq!(folded.split_terminator("\r\n").nth(2).unwrap().len(), 75);
+        assert_eq!(
+            parse(&folded)
+                .unwrap()
+                .property("DESCRIPTION")
+                .unwrap()
+                .value,
+            format!("{} continued", "é".repeat(100))
+        );
+    }
+
+    #[test]
+    fn parses_folded_lines_and_params() {
+        let calendar = parse(&calendar(
+            "DESCRIPTION;X-NOTE=\"a;b:c\":Lecturer: Jane\r\n  Doe\r\n",
+        ))
+        .unwrap();
+        let event = calendar.components("VEVENT").next().unwrap();
+        let description = event.property("DESCRIPTION").unwrap();
+
+        assert_eq!(description.value, "Lecturer: Jane Doe");
+        assert_eq!(description.param("x-note"), Some("a;b:c"));
+        assert_eq!(description.line, 17);
+    }
+
+    #[test]
+    fn expands_weekly_instances() {
+        let ical = calendar(
+            "RRULE:FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20240911T023000Z\r\n\
+             EXDATE;TZID=Asia/Dhaka:20240904T083000\r\n\
+             RDATE;TZID=Asia/Dhaka:20240907T083000\r\n",
+        );
+        validate(&ical).unwrap();
+        let calendar = parse(&ical).unwrap();
+
+        assert_eq!(
+            instances(&calendar, calendar.components("VEVENT").next().unwrap()).unwrap(),
+            vec![
+                datetime(2024, 9, 2, 8, 30),
+                datetime(2024, 9, 7, 8, 30),
+                datetime(2024, 9, 9, 8, 30),
+                datetime(2024, 9, 11, 8, 30),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_durations() {
+        let duration = |value: &str| {
+            parse_duration(&Property {
+                name: "DURATION".to_string(),
+                params: Vec::new(),
+                value: value.to_string(),
+                line: 1,
+            })
+        };
+
+        assert_eq!(duration("PT1H30M").unwrap(), Duration::minutes(90));
+        assert_eq!(duration("P1W").unwrap(), Duration::weeks(1));
+        assert_eq!(
+            duration("P1DT2H3M4S").unwrap(),
+            Duration::seconds(24 * 60 * 60 + 2 * 60 * 60 + 3 * 60 + 4)
+        );
+        assert_eq!(duration("-PT10M").unwrap(), Duration::minutes(-10));
+        for value in ["", "P", "PT", "PT1H30", "PT30M1H", "P1H", "1H"] {
+            assert!(duration(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_calendars() {
+        let invalid_events = [
+            // rule parts as parameters
+            "RRULE;BYDAY=MO;UNTIL=20241219:FREQ=WEEKLY\r\n",
+            // floating UNTIL with a zoned DTSTART
+            "RRULE:FREQ=WEEKLY;BYDAY=MO;UNTIL=20241219\r\n",
+            "RRULE:FREQ=WEEKLY;COUNT=3;UNTIL=20241219T000000Z\r\n",
+            "EXDATE;TZID=Asia/Dhaka:20240909\r\n",
+            "RECURRENCE-ID;TZID=Asia/Kolkata:20240909T083000\r\n",
+            "DTSTART;TZID=Asia/Dhaka:20240909T083000\r\n",
+            "BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT10M\r\nEND:VALARM\r\n",
+            "SUMMARY:This summary is long enough that it has to be folded onto a second line\r\n",
+            "DURATION:PT1H30M\r\n",
+        ];
+        for event in invalid_events {
+            assert!(
+                matches!(
+                    validate(&calendar(event)),
+                    Err(TimetableError::InvalidCalendar { .. })
+                ),
+                "{}",
+                event
+            );
+        }
+
+        let err = validate(&calendar("").replace("UID:1@test\r\n", "")).unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::InvalidCalendar { line: 12, .. }
+        ));
+        assert!(validate(&calendar("").replace("\r\n", "\n")).is_err());
+    }
+}
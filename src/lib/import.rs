@@ -0,0 +1,318 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+use crate::{
+    calendar::{DHAKA_UTC_OFFSET, LAB_CATEGORY},
+    courses::Course,
+    error::{Result, TimetableError},
+    icalendar::{self, Component, DateValue, Property},
+    periods::{Period, Time, Weekday},
+};
+
+/// Undoes the escaping of an iCalendar TEXT value
+pub fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some(escaped @ ('\\' | ',' | ';'))) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+fn text(event: &Component, name: &str) -> Option<String> {
+    event
+        .property(name)
+        .map(|property| unescape_text(&property.value))
+}
+
+/// A DTSTART or DTEND of a class as written, and converted to local time
+fn date_time(property: &Property) -> Result<(NaiveDateTime, NaiveDateTime)> {
+    match icalendar::date_values(property)?.as_slice() {
+        [DateValue::Local(datetime)] => Ok((*datetime, *datetime)),
+        [DateValue::Utc(datetime)] => Ok((*datetime, *datetime + DHAKA_UTC_OFFSET)),
+        _ => Err(TimetableError::InvalidCalendar {
+            line: property.line,
+            message: format!("{} of a class must be a date-time", property.name),
+        }),
+    }
+}
+
+/// Splits a summary like "CSE101 (2)" into the course code and section
+fn parse_summary(summary: &str, line: usize) -> Result<(String, u8)> {
+    summary
+        .trim()
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once('('))
+        .and_then(|(code, section)| {
+            let section = section.trim().parse().ok()?;
+            Some((code.trim().to_string(), section))
+        })
+        .filter(|(code, _)| !code.is_empty())
+        .ok_or_else(|| TimetableError::InvalidCalendar {
+            line,
+            message: format!(
+                "expected a summary like \"CSE101 (2)\", got \"{}\"",
+                summary
+            ),
+        })
+}
+
+/// Name of a calendar as given by X-WR-CALNAME or NAME, if it has one
+pub fn parse_calendar_name(ical: &str) -> Result<Option<String>> {
+    let calendar = icalendar::parse(ical)?;
+    Ok(text(&calendar, "X-WR-CALNAME").or_else(|| text(&calendar, "NAME")))
+}
+
+/// Read the courses back out of a timetable calendar, such as one written by
+/// [`crate::calendar::build_timetable`]. Every weekly event is a class; one-off
+/// events such as exams, holidays and rescheduled classes are skipped.
+pub fn parse_timetable(ical: &str) -> Result<Vec<Course>> {
+    let calendar = icalendar::parse(ical)?;
+    let mut courses = Vec::<Course>::new();
+
+    for event in calendar.components("VEVENT") {
+        let Some(rrule) = event.property("RRULE") else {
+            continue;
+        };
+        if event.property("RECURRENCE-ID").is_some() {
+            continue;
+        }
+
+        let dtstart = event
+            .property("DTSTART")
+            .ok_or_else(|| TimetableError::InvalidCalendar {
+                line: event.line,
+                message: "class without DTSTART".to_string(),
+            })?;
+        let recurrence = icalendar::parse_recurrence(rrule, dtstart)?;
+        if recurrence.freq != "WEEKLY" {
+            continue;
+        }
+
+        let (written_start, start) = date_time(dtstart)?;
+        let (end, end_line) = match (event.property("DTEND"), event.property("DURATION")) {
+            (Some(dtend), _) => (date_time(dtend)?.1, dtend.line),
+            (None, Some(duration)) => (start + icalendar::parse_duration(duration)?, duration.line),
+            (None, None) => {
+                return Err(TimetableError::InvalidCalendar {
+                    line: event.line,
+                    message: "class without DTEND or DURATION".to_string(),
+                })
+            }
+        };
+        if end.date() != start.date() || end.time() <= start.time() {
+            return Err(TimetableError::InvalidCalendar {
+                line: end_line,
+                message: "a class must end after it starts on the same day".to_string(),
+            });
+        }
+        let summary = text(event, "SUMMARY").unwrap_or_default();
+        let (course_code, section) = parse_summary(&summary, event.line)?;
+        let room = text(event, "LOCATION").unwrap_or_default();
+        let lab = text(event, "CATEGORIES").is_some_and(|categories| {
+            categories
+                .split(',')
+                .any(|category| category.trim().eq_ignore_ascii_case(LAB_CATEGORY))
+        });
+        let lecturer = text(event, "DESCRIPTION")
+            .map(|description| {
+                description
+                    .strip_prefix("Lecturer:")
+                    .unwrap_or(&description)
+                    .trim()
+                    .to_string()
+            })
+            .unwrap_or_default();
+
+        // BYDAY is in the time zone of DTSTART, so the days of a class
+        // written in UTC move with it when it's converted to local time
+        let day_shift = (start.date() - written_start.date()).num_days();
+        let days = if recurrence.by_day.is_empty() {
+            vec![start.weekday()]
+        } else {
+            recurrence
+                .by_day
+                .into_iter()
+                .map(|day| (0..day_shift).fold(day, |day, _| day.succ()))
+                .collect()
+        };
+        let mut periods = days
+            .into_iter()
+            .map(|day| Period {
+                day: Weekday::from(day),
+                start_time: Time::new(start.hour() as u8, start.minute() as u8),
+                end_time: Time::new(end.hour() as u8, end.minute() as u8),
+                room: room.clone(),
+                lab,
+            })
+            .collect::<Vec<_>>();
+
+        match courses
+            .iter_mut()
+            .find(|c| c.course_code == course_code && c.section == section)
+        {
+            Some(course) => course.periods.append(&mut periods),
+            None => courses.push(Course {
+                course_code,
+                section,
+                lecturer,
+                periods,
+            }),
+        }
+    }
+
+    Ok(courses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::{build_timetable, TimetableOptions};
+    use crate::test_utils::{date, period};
+
+    #[test]
+    fn round_trips_generated_calendar() {
+        let courses = vec![
+            Course {
+                course_code: "CSE101".to_string(),
+                section: 2,
+                lecturer: "Doe, Jane".to_string(),
+                periods: vec![
+                    period(Weekday::Monday, (8, 30), (10, 0), "AB3-302"),
+                    period(Weekday::Wednesday, (8, 30), (10, 0), "AB3-302"),
+                    Period {
+                        lab: true,
+                        ..period(Weekday::Thursday, (10, 10), (12, 10), "630")
+                    },
+                ],
+            },
+            Course {
+                course_code: "MAT101".to_string(),
+                section: 5,
+                lecturer: "John Smith".to_string(),
+                periods: vec![period(Weekday::Sunday, (13, 30), (15, 0), "FUB-201")],
+            },
+        ];
+        let options = TimetableOptions {
+            overrides: crate::overrides::parse_overrides("CSE101, 2024-10-21, move @ AB1-201")
+                .unwrap(),
+            exams: crate::exams::parse_exams("CSE101,final,2024-12-14,9:00AM,11:00AM").unwrap(),
+            holidays: crate::holidays::parse_holidays("2024-12-16, Victory Day").unwrap(),
+            ..Default::default()
+        };
+
+        let ical = build_timetable(
+            courses.clone(),
+            "Fall 2024",
+            date(2024, 9, 1),
+            date(2024, 12, 19),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(parse_timetable(&ical).unwrap(), courses);
+        assert_eq!(
+            parse_calendar_name(&ical).unwrap().as_deref(),
+            Some("Fall 2024")
+        );
+    }
+
+    #[test]
+    fn reads_hand_edited_calendar() {
+        let ical = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Student//EN\r\n\
+                    BEGIN:VEVENT\r\nUID:1\r\nDTSTAMP:20240801T000000Z\r\n\
+                    SUMMARY:CSE101 (2)\r\nLOCATION:AB3-302\r\n\
+                    DTSTART:20240902T083000\r\nDTEND:20240902T100000\r\n\
+                    RRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=20\r\n\
+                    END:VEVENT\r\nEND:VCALENDAR\r\n";
+
+        assert_eq!(
+            parse_timetable(ical).unwrap(),
+            vec![Course {
+                course_code: "CSE101".to_string(),
+                section: 2,
+                lecturer: String::new(),
+                periods: vec![
+                    period(Weekday::Monday, (8, 30), (10, 0), "AB3-302"),
+                    period(Weekday::Wednesday, (8, 30), (10, 0), "AB3-302"),
+                ],
+            }]
+        );
+
+        let err = parse_timetable(&ical.replace("CSE101 (2)", "Programming")).unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::InvalidCalendar { line: 4, .. }
+        ));
+
+        // a class running past midnight
+        let err = parse_timetable(&ical.replace("DTEND:20240902T100000", "DTEND:20240903T010000"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::InvalidCalendar { line: 10, .. }
+        ));
+    }
+
+    #[test]
+    fn reads_utc_times_and_durations() {
+        let ical = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Student//EN\r\n\
+                    BEGIN:VEVENT\r\nUID:1\r\nDTSTAMP:20240801T000000Z\r\n\
+                    SUMMARY:CSE101 (2)\r\nLOCATION:AB3-302\r\n\
+                    DTSTART:20240902T023000Z\r\nDURATION:PT1H30M\r\n\
+                    RRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=20\r\n\
+                    END:VEVENT\r\nEND:VCALENDAR\r\n";
+
+        let cse101 = |start: (u8, u8), end: (u8, u8), days: [Weekday; 2]| {
+            vec![Course {
+                course_code: "CSE101".to_string(),
+                section: 2,
+                lecturer: String::new(),
+                periods: days
+                    .into_iter()
+                    .map(|day| period(day, start, end, "AB3-302"))
+                    .collect(),
+            }]
+        };
+        assert_eq!(
+            parse_timetable(ical).unwrap(),
+            cse101((8, 30), (10, 0), [Weekday::Monday, Weekday::Wednesday])
+        );
+        assert_eq!(
+            parse_timetable(&ical.replace("DURATION:PT1H30M", "DTEND:20240902T040000Z")).unwrap(),
+            cse101((8, 30), (10, 0), [Weekday::Monday, Weekday::Wednesday])
+        );
+
+        // 19:00 on Sunday in UTC is 01:00 on Monday in Dhaka
+        let late = ical.replace("DTSTART:20240902T023000Z", "DTSTART:20240901T190000Z");
+        assert_eq!(
+            parse_timetable(&late.replace("BYDAY=MO,WE", "BYDAY=SU,TU")).unwrap(),
+            cse101((1, 0), (2, 30), [Weekday::Monday, Weekday::Wednesday])
+        );
+
+        let err = parse_timetable(&ical.replace("DURATION:PT1H30M\r\n", "")).unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::InvalidCalendar { line: 4, .. }
+        ));
+    }
+
+    #[test]
+    fn unescapes_text() {
+        assert_eq!(
+            unescape_text(r"Doe\, Jane\; Roe\\Lab\nRoom"),
+            "Doe, Jane; Roe\\Lab\nRoom"
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! In-process stand-in for the EWU portal, used by the server and integration
+//! tests. Only built with the `mock-portal` feature, which the tests turn on,
+//! so its fixed credentials never end up in a release build.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Mutex,
+    },
+};
+
+use actix_web::{
+    dev::ServerHandle, get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use serde_json::json;
+
+use crate::portal::PortalConfig;
+
+pub const USERNAME: &str = "2021-1-60-001";
+pub const PASSWORD: &str = "hunter2";
+pub const STUDENT_NAME: &str = "Test Student";
+pub const FIRST_NO: i8 = 4;
+pub const SECOND_NO: i8 = 7;
+pub const SESSION_COOKIE: &str = "ASP.NET_SessionId";
+/// Name of both the hidden field and the cookie carrying the anti-forgery token
+pub const ANTI_FORGERY_FIELD: &str = "__RequestVerificationToken";
+
+/// Portal data served by [`MockPortal`]
+#[derive(Debug, Clone)]
+pub struct MockData {
+    pub username: String,
+    pub password: String,
+    /// Anti-forgery token the login form and cookie carry, which logins have
+    /// to post back. None for a login form without one.
+    pub anti_forgery_token: Option<String>,
+    /// Response of `GetSemesterForDropDown`
+    pub semesters: serde_json::Value,
+    /// Responses of `GetSemesterStudentWiseAdvisingCourseListStudent`, keyed by semester ID
+    pub courses: HashMap<u16, serde_json::Value>,
+}
+
+impl Default for MockData {
+    fn default() -> Self {
+        let mut courses = HashMap::new();
+        courses.insert(
+            1,
+            json!([
+                {
+                    "CourseCode": "CSE101",
+                    "CourseTitle": "Structured Programming",
+                    "Credits": 3.0,
+                    "SectionName": 2,
+                    "TimeSlotName": "MW 8:30AM-10:00AM",
+                    "RoomName": "AB3-302",
+                    "FacultyName": "Jane Doe",
+                    "FacultyShortName": "JD",
+                    "DropStatus": "No",
+                    "WithDrawStatus": "No"
+                },
+                {
+                    "CourseCode": "CSE101",
+                    "CourseTitle": "Structured Programming Lab",
+                    "Credits": 1.0,
+                    "SectionName": 2,
+                    "TimeSlotName": "R 10:10AM-12:10PM",
+                    "RoomName": "630",
+                    "FacultyName": "Jane Doe",
+                    "FacultyShortName": "JD",
+                    "DropStatus": "No",
+                    "WithDrawStatus": "No"
+                },
+                {
+                    "CourseCode": "MAT101",
+                    "CourseTitle": "Differential Calculus",
+                    "Credits": 3.0,
+                    "SectionName": 5,
+                    "TimeSlotName": "ST 1:30PM-3:00PM",
+                    "RoomName": "FUB-201",
+                    "FacultyName": "John Smith",
+                    "FacultyShortName": "JS",
+                    "DropStatus": "No",
+                    "WithDrawStatus": "No"
+                },
+                {
+                    "CourseCode": "ENG101",
+                    "CourseTitle": "Basic English",
+                    "Credits": 3.0,
+                    "SectionName": 1,
+                    "TimeSlotName": "MW 3:10PM-4:40PM",
+                    "RoomName": "AB1-101",
+                    "FacultyName": "Alex Roe",
+                    "FacultyShortName": "AR",
+                    "DropStatus": "Yes",
+                    "WithDrawStatus": "No"
+                }
+            ]),
+        );
+
+        MockData {
+            username: USERNAME.to_string(),
+            password: PASSWORD.to_string(),
+            anti_forgery_token: None,
+            semesters: json!([
+                {
+                    "SemesterId": 1,
+                    "SemesterName": "Fall-2024",
+                    "StartingDate": "2024-09-01T00:00:00",
+                    "EndingDate": "2024-12-19T00:00:00"
+                },
+                {
+                    "SemesterId": 2,
+                    "SemesterName": "Spring-2025",
+                    "StartingDate": "2025-01-12T00:00:00",
+                    "EndingDate": "2025-05-01T00:00:00"
+                }
+            ]),
+            courses,
+        }
+    }
+}
+
+struct MockState {
+    data: MockData,
+    next_session: AtomicU64,
+    /// Session IDs mapped to whether they have logged in
+    sessions: Mutex<HashMap<String, bool>>,
+}
+
+impl MockState {
+    fn is_authenticated(&self, req: &HttpRequest) -> bool {
+        let Some(cookie) = req.cookie(SESSION_COOKIE) else {
+            return false;
+        };
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(cookie.value()).copied().unwrap_or(false)
+    }
+}
+
+fn login_page(error: Option<&str>, anti_forgery_token: Option<&str>) -> String {
+    let error = error
+        .map(|msg| format!(r#"<div class="error">{}</div>"#, msg))
+        .unwrap_or_default();
+    let anti_forgery = anti_forgery_token
+        .map(|token| {
+            format!(r#"<input type="hidden" name="{ANTI_FORGERY_FIELD}" value="{token}">"#)
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<body>
+    {error}
+    <form method="get" action="/Search">
+        <input type="hidden" name="Lang" value="en">
+        <input type="hidden" name="{ANTI_FORGERY_FIELD}" value="not-the-login-form">
+        <input type="text" name="Query">
+    </form>
+    <form method="post" action="/">
+        {anti_forgery}
+        <input type="text" name="Username">
+        <input type="password" name="Password">
+        <input type="hidden" name="FirstNo" value="{FIRST_NO}">
+        <input type="hidden" name="SecondNo" value="{SECOND_NO}">
+        <input type="text" name="Answer">
+    </form>
+</body>
+</html>"#
+    )
+}
+
+fn home_page() -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<body>
+    <div class="nav-user"><span>{STUDENT_NAME}</span></div>
+</body>
+</html>"#
+    )
+}
+
+#[get("/")]
+async fn index(state: web::Data<MockState>) -> impl Responder {
+    let session_id = format!(
+        "mock{:016x}",
+        state.next_session.fetch_add(1, Ordering::Relaxed)
+    );
+    state
+        .sessions
+        .lock()
+        .unwrap()
+        .insert(session_id.clone(), false);
+
+    let mut res = HttpResponse::Ok();
+    res.content_type("text/html").append_header((
+        "set-cookie",
+        format!("{SESSION_COOKIE}={session_id}; path=/; HttpOnly"),
+    ));
+    if let Some(token) = &state.data.anti_forgery_token {
+        res.append_header((
+            "set-cookie",
+            format!("{ANTI_FORGERY_FIELD}={token}; path=/; HttpOnly"),
+        ));
+    }
+    res.body(login_page(None, state.data.anti_forgery_token.as_deref()))
+}
+
+#[post("/")]
+async fn login(
+    req: HttpRequest,
+    form: web::Form<HashMap<String, String>>,
+    state: web::Data<MockState>,
+) -> impl Responder {
+    let Some(cookie) = req.cookie(SESSION_COOKIE) else {
+        return HttpResponse::BadRequest().body("Missing session");
+    };
+
+    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or_default();
+    let answer = (FIRST_NO + SECOND_NO).to_string();
+
+    let anti_forgery_token = state.data.anti_forgery_token.as_deref();
+    if let Some(token) = anti_forgery_token {
+        let token_cookie = req.cookie(ANTI_FORGERY_FIELD);
+        if field(ANTI_FORGERY_FIELD) != token
+            || token_cookie.as_ref().map(|c| c.value()) != Some(token)
+        {
+            // ASP.NET answers a missing or wrong token with an error page
+            return HttpResponse::BadRequest().body("The anti-forgery token is invalid");
+        }
+    }
+
+    let error = if field("FirstNo") != FIRST_NO.to_string()
+        || field("SecondNo") != SECOND_NO.to_string()
+        || field("Answer") != answer
+    {
+        Some("Wrong answer to the captcha")
+    } else if field("Username") != state.data.username || field("Password") != state.data.password {
+        Some("Invalid username or password")
+    } else {
+        None
+    };
+
+    let mut sessions = state.sessions.lock().unwrap();
+    match (error, sessions.get_mut(cookie.value())) {
+        (_, None) => HttpResponse::BadRequest().body("Unknown session"),
+        (Some(error), Some(_)) => HttpResponse::Ok()
+            .content_type("text/html")
+            .body(login_page(Some(error), anti_forgery_token)),
+        (None, Some(authenticated)) => {
+            *authenticated = true;
+            HttpResponse::Ok()
+                .content_type("text/html")
+                .body(home_page())
+        }
+    }
+}
+
+#[get("/api/utility/GetSemesterForDropDown")]
+async fn semester_list(req: HttpRequest, state: web::Data<MockState>) -> impl Responder {
+    if !state.is_authenticated(&req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    HttpResponse::Ok().json(&state.data.semesters)
+}
+
+#[get("/api/Advising/GetSemesterStudentWiseAdvisingCourseListStudent/{semester_id}")]
+async fn course_list(
+    req: HttpRequest,
+    path: web::Path<u16>,
+    state: web::Data<MockState>,
+) -> impl Responder {
+    if !state.is_authenticated(&req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let courses = state
+        .data
+        .courses
+        .get(&path.into_inner())
+        .cloned()
+        .unwrap_or_else(|| json!([]));
+    HttpResponse::Ok().json(courses)
+}
+
+/// A fake portal listening on a random local port, stopped when dropped
+pub struct MockPortal {
+    addr: SocketAddr,
+    handle: ServerHandle,
+}
+
+impl MockPortal {
+    /// Starts a portal serving [`MockData::default`]
+    pub fn start() -> std::io::Result<Self> {
+        Self::start_with(MockData::default())
+    }
+
+    /// Starts a portal serving `data` on its own thread
+    pub fn start_with(data: MockData) -> std::io::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                let state = web::Data::new(MockState {
+                    data,
+                    next_session: AtomicU64::new(1),
+                    sessions: Mutex::new(HashMap::new()),
+                });
+
+                let server = HttpServer::new(move || {
+                    App::new()
+                        .app_data(state.clone())
+                        .service(index)
+                        .service(login)
+                        .service(semester_list)
+                        .service(course_list)
+                })
+                .workers(1)
+                .disable_signals()
+                .bind(("127.0.0.1", 0));
+
+                let server = match server {
+                    Ok(server) => server,
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+                let addr = server.addrs()[0];
+                let server = server.run();
+                let _ = tx.send(Ok((addr, server.handle())));
+                let _ = server.await;
+            });
+        });
+
+        let (addr, handle) = rx.recv().map_err(std::io::Error::other)??;
+
+        Ok(MockPortal { addr, handle })
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn config(&self) -> PortalConfig {
+        PortalConfig::new(&self.base_url())
+    }
+}
+
+impl Drop for MockPortal {
+    fn drop(&mut self) {
+        // the stop command is sent eagerly, the returned future only waits for completion
+        drop(self.handle.stop(false));
+    }
+}
@@ -1,6 +1,21 @@
 pub mod auth;
 pub mod calendar;
+pub mod conflicts;
 pub mod courses;
+pub mod diff;
+pub mod error;
+pub mod exams;
+pub mod holidays;
+pub mod icalendar;
+pub mod import;
+#[cfg(feature = "mock-portal")]
+pub mod mock_portal;
+pub mod overrides;
+pub mod pdf;
 pub mod periods;
+pub mod portal;
 pub mod semester;
+#[cfg(test)]
+mod test_utils;
+pub mod time_slot;
 pub mod utils;
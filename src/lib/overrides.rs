@@ -0,0 +1,282 @@
+use std::fmt::Display;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{Result, TimetableError},
+    periods::Time,
+    time_slot,
+};
+
+const RANGE_SEPARATORS: [char; 2] = ['-', '–'];
+
+/// What happens to a course on the date of an [`Override`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OverrideChange {
+    /// An extra session such as a make-up class, in the course's room unless given
+    Add {
+        start_time: Time,
+        end_time: Time,
+        room: Option<String>,
+    },
+    /// The classes of the course on this date don't take place
+    Cancel,
+    /// The classes of the course on this date are moved, unset fields are kept
+    Move {
+        to_date: Option<NaiveDate>,
+        times: Option<(Time, Time)>,
+        room: Option<String>,
+    },
+}
+
+/// A one-off change to a course's weekly schedule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Override {
+    pub course_code: String,
+    /// Date of the added session, or of the classes that are cancelled or moved
+    pub date: NaiveDate,
+    pub change: OverrideChange,
+}
+
+fn parse_time_range(range: &str, line: usize) -> Result<(Time, Time)> {
+    let invalid = |message: String| TimetableError::InvalidOverride { line, message };
+
+    let (start, end) = range
+        .split_once(RANGE_SEPARATORS)
+        .ok_or_else(|| invalid(format!("expected \"<start>-<end>\", got \"{}\"", range)))?;
+    let start = time_slot::parse_time(start).map_err(|e| invalid(e.to_string()))?;
+    let end = time_slot::parse_time(end).map_err(|e| invalid(e.to_string()))?;
+    if (end.hours, end.minutes) <= (start.hours, start.minutes) {
+        return Err(invalid(format!("{} is not after {}", end, start)));
+    }
+
+    Ok((start, end))
+}
+
+fn parse_change(change: &str, line: usize) -> Result<OverrideChange> {
+    let invalid = |message: String| TimetableError::InvalidOverride { line, message };
+
+    let (change, room) = match change.split_once('@') {
+        Some((change, room)) if !room.trim().is_empty() => (change, Some(room.trim().to_string())),
+        Some(_) => return Err(invalid("expected a room after '@'".to_string())),
+        None => (change, None),
+    };
+    let (action, args) = change
+        .trim()
+        .split_once(char::is_whitespace)
+        .unwrap_or((change.trim(), ""));
+    let args = args.trim();
+
+    match action.to_ascii_lowercase().as_str() {
+        "cancel" if args.is_empty() && room.is_none() => Ok(OverrideChange::Cancel),
+        "cancel" => Err(invalid("\"cancel\" takes no arguments".to_string())),
+        "add" => {
+            let (start_time, end_time) = parse_time_range(args, line)?;
+            Ok(OverrideChange::Add {
+                start_time,
+                end_time,
+                room,
+            })
+        }
+        "move" => {
+            let (first, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let (to_date, times) = match NaiveDate::parse_from_str(first, "%Y-%m-%d") {
+                Ok(to_date) => (Some(to_date), rest.trim()),
+                Err(_) => (None, args),
+            };
+            let times = match times {
+                "" => None,
+                times => Some(parse_time_range(times, line)?),
+            };
+            if to_date.is_none() && times.is_none() && room.is_none() {
+                return Err(invalid(
+                    "\"move\" needs a date, a time range or a room".to_string(),
+                ));
+            }
+
+            Ok(OverrideChange::Move {
+                to_date,
+                times,
+                room,
+            })
+        }
+        _ => Err(invalid(format!(
+            "expected \"add\", \"cancel\" or \"move\", got \"{}\"",
+            action
+        ))),
+    }
+}
+
+/// Parse one schedule change per line in the form "<course>, <date>, <change>", where
+/// the change is one of
+///
+/// - "cancel"
+/// - "add <start>-<end> [@ <room>]"
+/// - "move [<date>] [<start>-<end>] [@ <room>]"
+///
+/// Dates are "YYYY-MM-DD" and times are 12-hr like "8:30AM".
+/// Blank lines and lines starting with '#' are ignored.
+pub fn parse_overrides(text: &str) -> Result<Vec<Override>> {
+    let mut overrides = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: String| TimetableError::InvalidOverride {
+            line: index + 1,
+            message,
+        };
+
+        let mut fields = line.splitn(3, ',').map(str::trim);
+        let (Some(course_code), Some(date), Some(change)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid(
+                "expected \"<course>, <date>, <change>\"".to_string(),
+            ));
+        };
+        if course_code.is_empty() {
+            return Err(invalid("missing course code".to_string()));
+        }
+
+        overrides.push(Override {
+            course_code: course_code.to_string(),
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|e| invalid(format!("\"{}\": {}", date, e)))?,
+            change: parse_change(change, index + 1)?,
+        });
+    }
+
+    Ok(overrides)
+}
+
+impl Display for Override {
+    /// Formats the override as a line read by [`parse_overrides`]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}, ", self.course_code, self.date)?;
+
+        let room = match &self.change {
+            OverrideChange::Cancel => return write!(f, "cancel"),
+            OverrideChange::Add {
+                start_time,
+                end_time,
+                room,
+            } => {
+                write!(f, "add {}-{}", start_time, end_time)?;
+                room
+            }
+            OverrideChange::Move {
+                to_date,
+                times,
+                room,
+            } => {
+                write!(f, "move")?;
+                if let Some(to_date) = to_date {
+                    write!(f, " {}", to_date)?;
+                }
+                if let Some((start_time, end_time)) = times {
+                    write!(f, " {}-{}", start_time, end_time)?;
+                }
+                room
+            }
+        };
+
+        match room {
+            Some(room) => write!(f, " @ {}", room),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Formats overrides in the text format read by [`parse_overrides`]
+pub fn format_overrides(overrides: &[Override]) -> String {
+    overrides.iter().map(|o| format!("{}\n", o)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::date;
+
+    #[test]
+    fn parses_override_lines() {
+        let overrides = parse_overrides(
+            "# make-up classes\n\
+             CSE101, 2024-10-21, cancel\n\
+             \n\
+             CSE101, 2024-10-26, add 8:30AM-10:00AM @ AB1-201\n\
+             MAT101, 2024-10-22, move 2024-10-24\n\
+             MAT101, 2024-10-29, move 1:00PM-2:30PM @ FUB-401",
+        )
+        .unwrap();
+
+        assert_eq!(
+            overrides,
+            vec![
+                Override {
+                    course_code: "CSE101".to_string(),
+                    date: date(2024, 10, 21),
+                    change: OverrideChange::Cancel,
+                },
+                Override {
+                    course_code: "CSE101".to_string(),
+                    date: date(2024, 10, 26),
+                    change: OverrideChange::Add {
+                        start_time: Time::new(8, 30),
+                        end_time: Time::new(10, 0),
+                        room: Some("AB1-201".to_string()),
+                    },
+                },
+                Override {
+                    course_code: "MAT101".to_string(),
+                    date: date(2024, 10, 22),
+                    change: OverrideChange::Move {
+                        to_date: Some(date(2024, 10, 24)),
+                        times: None,
+                        room: None,
+                    },
+                },
+                Override {
+                    course_code: "MAT101".to_string(),
+                    date: date(2024, 10, 29),
+                    change: OverrideChange::Move {
+                        to_date: None,
+                        times: Some((Time::new(13, 0), Time::new(14, 30))),
+                        room: Some("FUB-401".to_string()),
+                    },
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse_overrides(&format_overrides(&overrides)).unwrap(),
+            overrides
+        );
+    }
+
+    #[test]
+    fn reports_invalid_line() {
+        for text in [
+            "CSE101, 2024-10-21",
+            "CSE101, 2024-10-21, postpone",
+            "CSE101, 2024-10-21, add 10:00AM-8:30AM",
+            "CSE101, 2024-10-21, move",
+            "CSE101, 2024-13-21, cancel",
+        ] {
+            let err =
+                parse_overrides(&format!("CSE101, 2024-10-21, cancel\n{}", text)).unwrap_err();
+            assert!(
+                matches!(err, TimetableError::InvalidOverride { line: 2, .. }),
+                "{}: {:?}",
+                text,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+
+use crate::{
+    courses::Course,
+    periods::{Time, Weekday},
+};
+
+/// A4 landscape, in points
+const PAGE_WIDTH: f32 = 842.0;
+const PAGE_HEIGHT: f32 = 595.0;
+const MARGIN: f32 = 36.0;
+const TITLE_HEIGHT: f32 = 30.0;
+const DAY_HEADER_HEIGHT: f32 = 18.0;
+const HOUR_COLUMN_WIDTH: f32 = 44.0;
+const LINE_HEIGHT: f32 = 9.0;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+
+/// Encodes text for the standard fonts, which use WinAnsiEncoding. Characters
+/// the encoding lacks are replaced with '?'.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            '–' => 0x96,
+            '—' => 0x97,
+            c if (c as u32) < 0x80 || (0xA0..=0xFF).contains(&(c as u32)) => c as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Cuts text down to roughly what fits in `width` points, since the standard
+/// fonts' metrics aren't available. Helvetica averages about half its size per character.
+fn fit(text: &str, width: f32, size: f32) -> String {
+    let max_chars = (width / (size * 0.5)) as usize;
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut fitted = text
+        .chars()
+        .take(max_chars.saturating_sub(1))
+        .collect::<String>();
+    fitted.push('.');
+    fitted
+}
+
+/// Colour of the `index`th course, the same hues as the web week grid
+fn course_colour(index: usize) -> (f32, f32, f32) {
+    // HSL with 70% saturation and 85% lightness
+    let hue = (index * 137 % 360) as f32 / 60.0;
+    let chroma = (1.0 - (2.0 * 0.85 - 1.0_f32).abs()) * 0.7;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = 0.85 - chroma / 2.0;
+    (r + m, g + m, b + m)
+}
+
+fn text(content: &mut Content, font: Name, size: f32, x: f32, y: f32, text: &str) {
+    content
+        .begin_text()
+        .set_font(font, size)
+        .next_line(x, y)
+        .show(Str(&win_ansi(text)))
+        .end_text();
+}
+
+/// Renders the courses as a printable weekly grid on a single A4 page, with
+/// days as columns and time running down the page. Each class shows the
+/// course code, section, whether it is a lab, time, room and lecturer as far
+/// as they fit.
+pub fn build_timetable_pdf(courses: &[Course], title: &str) -> Vec<u8> {
+    let classes = courses
+        .iter()
+        .enumerate()
+        .flat_map(|(index, course)| course.periods.iter().map(move |p| (index, course, p)))
+        .collect::<Vec<_>>();
+
+    let first_hour = classes
+        .iter()
+        .map(|(_, _, period)| period.start_time.hours as u16)
+        .min()
+        .unwrap_or(8);
+    let last_hour = classes
+        .iter()
+        .map(|(_, _, period)| period.end_time.minutes().div_ceil(60))
+        .max()
+        .unwrap_or(17)
+        .max(first_hour + 1);
+
+    let grid_top = PAGE_HEIGHT - MARGIN - TITLE_HEIGHT - DAY_HEADER_HEIGHT;
+    let grid_left = MARGIN + HOUR_COLUMN_WIDTH;
+    let day_width = (PAGE_WIDTH - MARGIN - grid_left) / Weekday::ALL.len() as f32;
+    let minute_height = (grid_top - MARGIN) / ((last_hour - first_hour) * 60) as f32;
+    // classes that end before they start are drawn as empty boxes
+    let y = |minutes: u16| {
+        let minutes = minutes.clamp(first_hour * 60, last_hour * 60);
+        grid_top - (minutes - first_hour * 60) as f32 * minute_height
+    };
+    let x = |day: Weekday| grid_left + day.index() as f32 * day_width;
+
+    let mut content = Content::new();
+    text(
+        &mut content,
+        BOLD,
+        16.0,
+        MARGIN,
+        PAGE_HEIGHT - MARGIN - 16.0,
+        title,
+    );
+
+    content.set_fill_rgb(0.0, 0.0, 0.0);
+    for day in Weekday::ALL {
+        text(
+            &mut content,
+            BOLD,
+            10.0,
+            x(day) + 4.0,
+            grid_top + 5.0,
+            day.name(),
+        );
+    }
+
+    content.set_stroke_rgb(0.75, 0.75, 0.75).set_line_width(0.5);
+    for hour in first_hour..=last_hour {
+        content
+            .move_to(MARGIN, y(hour * 60))
+            .line_to(PAGE_WIDTH - MARGIN, y(hour * 60))
+            .stroke();
+        if hour < last_hour {
+            let label = Time::new(hour as u8, 0).to_string();
+            text(
+                &mut content,
+                REGULAR,
+                8.0,
+                MARGIN,
+                y(hour * 60) - 9.0,
+                &label,
+            );
+        }
+    }
+    for day in Weekday::ALL {
+        content
+            .move_to(x(day), grid_top + DAY_HEADER_HEIGHT)
+            .line_to(x(day), MARGIN)
+            .stroke();
+    }
+
+    content.set_stroke_rgb(0.3, 0.3, 0.3);
+    for (index, course, period) in &classes {
+        let left = x(period.day) + 1.0;
+        let width = day_width - 2.0;
+        let top = y(period.start_time.minutes());
+        let bottom = y(period.end_time.minutes()).min(top);
+
+        let (r, g, b) = course_colour(*index);
+        content
+            .set_fill_rgb(r, g, b)
+            .rect(left, bottom, width, top - bottom)
+            .fill_nonzero_and_stroke();
+
+        content.set_fill_rgb(0.0, 0.0, 0.0);
+        let lines = [
+            (
+                BOLD,
+                format!(
+                    "{} ({}){}",
+                    course.course_code,
+                    course.section,
+                    if period.lab { " Lab" } else { "" }
+                ),
+            ),
+            (
+                REGULAR,
+                format!("{}–{}", period.start_time, period.end_time),
+            ),
+            (REGULAR, period.room.clone()),
+            (REGULAR, course.lecturer.clone()),
+        ];
+        let fitting = ((top - bottom - 2.0) / LINE_HEIGHT) as usize;
+        for (line, (font, line_text)) in lines.iter().take(fitting).enumerate() {
+            text(
+                &mut content,
+                *font,
+                7.5,
+                left + 3.0,
+                top - LINE_HEIGHT * (line + 1) as f32,
+                &fit(line_text, width - 6.0, 7.5),
+            );
+        }
+    }
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let page_id = Ref::new(3);
+    let regular_id = Ref::new(4);
+    let bold_id = Ref::new(5);
+    let content_id = Ref::new(6);
+    let info_id = Ref::new(7);
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids([page_id]).count(1);
+
+    let mut page = pdf.page(page_id);
+    page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+    page.parent(page_tree_id);
+    page.contents(content_id);
+    page.resources()
+        .fonts()
+        .pair(REGULAR, regular_id)
+        .pair(BOLD, bold_id);
+    page.finish();
+
+    pdf.type1_font(regular_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.stream(content_id, &content.finish());
+    pdf.document_info(info_id).title(TextStr(title));
+
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::periods::Period;
+
+    #[test]
+    fn renders_classes() {
+        let courses = vec![Course {
+            course_code: "CSE101".to_string(),
+            section: 2,
+            lecturer: "Jane Doe".to_string(),
+            periods: vec![
+                Period {
+                    day: Weekday::Monday,
+                    start_time: Time::new(8, 30),
+                    end_time: Time::new(10, 0),
+                    room: "AB3-302".to_string(),
+                    lab: false,
+                },
+                Period {
+                    day: Weekday::Thursday,
+                    start_time: Time::new(10, 10),
+                    end_time: Time::new(12, 10),
+                    room: "630".to_string(),
+                    lab: true,
+                },
+            ],
+        }];
+
+        let pdf = build_timetable_pdf(&courses, "Fall 2024");
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with("%PDF-"));
+        for text in [
+            "(CSE101 (2)) Tj",
+            "(CSE101 (2) Lab) Tj",
+            "(AB3-302) Tj",
+            "(Jane Doe) Tj",
+            "(Monday) Tj",
+        ] {
+            assert!(pdf.contains(text), "{} not found", text);
+        }
+    }
+
+    #[test]
+    fn survives_class_ending_before_it_starts() {
+        let courses = vec![Course {
+            course_code: "CSE101".to_string(),
+            section: 2,
+            lecturer: "Jane Doe".to_string(),
+            periods: vec![Period {
+                day: Weekday::Monday,
+                start_time: Time::new(23, 0),
+                end_time: Time::new(1, 0),
+                room: "AB3-302".to_string(),
+                lab: false,
+            }],
+        }];
+
+        assert!(build_timetable_pdf(&courses, "Fall 2024").starts_with(b"%PDF-"));
+    }
+
+    #[test]
+    fn encodes_and_fits_text() {
+        assert_eq!(win_ansi("8–10 Café ✓"), b"8\x9610 Caf\xe9 ?");
+        assert_eq!(fit("Jane Doe", 100.0, 8.0), "Jane Doe");
+        assert_eq!(fit("Jane Doe", 20.0, 8.0), "Jane.");
+    }
+}
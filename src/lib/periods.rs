@@ -1,7 +1,15 @@
-use std::{error::Error, fmt::Display};
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{Result, TimetableError},
+    time_slot,
+};
 
 /// Stores 24-hr time
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Time {
     pub hours: u8,
     pub minutes: u8,
@@ -11,6 +19,11 @@ impl Time {
     pub fn new(hours: u8, minutes: u8) -> Self {
         Time { hours, minutes }
     }
+
+    /// Minutes since midnight
+    pub fn minutes(self) -> u16 {
+        self.hours as u16 * 60 + self.minutes as u16
+    }
 }
 
 impl Display for Time {
@@ -26,96 +39,144 @@ impl Display for Time {
 }
 
 impl TryFrom<&str> for Time {
-    type Error = Box<dyn Error>;
-
-    /// Parse a 12-hr time string in the format "HH:MMAM" or "HH:MMPM" into 24-hr
-    fn try_from(time: &str) -> Result<Time, Box<dyn Error>> {
-        let re = regex::Regex::new(r"(\d{1,2}):(\d{2})(AM|PM)")?;
-        let caps = re.captures(time).ok_or("Invalid time string")?;
-        let hours = caps.get(1).ok_or("Invalid hours")?.as_str().parse::<u8>()?;
-        let minutes = caps
-            .get(2)
-            .ok_or("Invalid minutes")?
-            .as_str()
-            .parse::<u8>()?;
-        let period = caps.get(3).ok_or("AM/PM not present")?.as_str();
-
-        let hours = match period {
-            "AM" => {
-                if hours == 12 {
-                    0
-                } else {
-                    hours
-                }
-            }
-            "PM" => {
-                if hours == 12 {
-                    hours
-                } else {
-                    hours + 12
-                }
-            }
-            _ => panic!("Invalid period"),
-        };
+    type Error = TimetableError;
 
-        Ok(Time { hours, minutes })
+    /// Parse a 12-hr time string such as "9:25AM" or "9 pm" into 24-hr
+    fn try_from(time: &str) -> Result<Time> {
+        time_slot::parse_time(time)
     }
 }
 
-/// Stores a time slot
-#[derive(Debug, PartialEq, Clone)]
-pub struct Period {
-    /// Stores an index from 0 to 6, where 0 is Sunday and 6 is Saturday
-    pub day: u8,
-    pub start_time: Time,
-    pub end_time: Time,
-    pub room: String,
+/// Day of the week, in the order the portal lists them starting from Sunday
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+pub enum Weekday {
+    Sunday,
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
 }
 
-impl Period {
-    pub fn parse_weekday_letter(day: char) -> u8 {
-        match day {
-            'S' => 0,
-            'M' => 1,
-            'T' => 2,
-            'W' => 3,
-            'R' => 4,
-            'F' => 5,
-            'A' => 6,
-            _ => panic!("Invalid day letter"),
+impl Weekday {
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Sunday,
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+    ];
+
+    /// Parse the single letter the portal uses for a day, e.g. 'R' for Thursday
+    pub fn from_letter(letter: char) -> Result<Self> {
+        match letter {
+            'S' => Ok(Weekday::Sunday),
+            'M' => Ok(Weekday::Monday),
+            'T' => Ok(Weekday::Tuesday),
+            'W' => Ok(Weekday::Wednesday),
+            'R' => Ok(Weekday::Thursday),
+            'F' => Ok(Weekday::Friday),
+            'A' => Ok(Weekday::Saturday),
+            _ => Err(TimetableError::InvalidTimeSlot(letter.to_string())),
         }
     }
 
-    pub fn weekday_name(day: u8) -> String {
-        match day {
-            0 => "Sunday".to_string(),
-            1 => "Monday".to_string(),
-            2 => "Tuesday".to_string(),
-            3 => "Wednesday".to_string(),
-            4 => "Thursday".to_string(),
-            5 => "Friday".to_string(),
-            6 => "Saturday".to_string(),
-            _ => panic!("Invalid day index"),
+    /// Index from 0 to 6, where 0 is Sunday and 6 is Saturday
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weekday::Sunday => "Sunday",
+            Weekday::Monday => "Monday",
+            Weekday::Tuesday => "Tuesday",
+            Weekday::Wednesday => "Wednesday",
+            Weekday::Thursday => "Thursday",
+            Weekday::Friday => "Friday",
+            Weekday::Saturday => "Saturday",
+        }
+    }
+
+    /// Two letter abbreviation used by iCalendar's BYDAY
+    pub fn two_letter(self) -> &'static str {
+        match self {
+            Weekday::Sunday => "SU",
+            Weekday::Monday => "MO",
+            Weekday::Tuesday => "TU",
+            Weekday::Wednesday => "WE",
+            Weekday::Thursday => "TH",
+            Weekday::Friday => "FR",
+            Weekday::Saturday => "SA",
         }
     }
+}
+
+impl Display for Weekday {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
 
-    pub fn parse_periods(value: &str, room: &str) -> Result<Vec<Self>, Box<dyn Error>> {
-        let mut split_str = value.split(" ");
-        let day_letters = split_str.next().ok_or("Garbage input")?;
-        let time_range = split_str.next().ok_or("Garbage input")?;
+impl From<Weekday> for chrono::Weekday {
+    fn from(day: Weekday) -> Self {
+        match day {
+            Weekday::Sunday => chrono::Weekday::Sun,
+            Weekday::Monday => chrono::Weekday::Mon,
+            Weekday::Tuesday => chrono::Weekday::Tue,
+            Weekday::Wednesday => chrono::Weekday::Wed,
+            Weekday::Thursday => chrono::Weekday::Thu,
+            Weekday::Friday => chrono::Weekday::Fri,
+            Weekday::Saturday => chrono::Weekday::Sat,
+        }
+    }
+}
 
-        let days = day_letters.chars().map(Period::parse_weekday_letter);
+impl From<chrono::Weekday> for Weekday {
+    fn from(day: chrono::Weekday) -> Self {
+        match day {
+            chrono::Weekday::Sun => Weekday::Sunday,
+            chrono::Weekday::Mon => Weekday::Monday,
+            chrono::Weekday::Tue => Weekday::Tuesday,
+            chrono::Weekday::Wed => Weekday::Wednesday,
+            chrono::Weekday::Thu => Weekday::Thursday,
+            chrono::Weekday::Fri => Weekday::Friday,
+            chrono::Weekday::Sat => Weekday::Saturday,
+        }
+    }
+}
 
-        let mut time_range = time_range.split("-");
-        let start_time: Time = time_range.next().ok_or("Invalid start time")?.try_into()?;
-        let end_time: Time = time_range.next().ok_or("Invalid time slot")?.try_into()?;
+/// Stores a time slot
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Period {
+    pub day: Weekday,
+    pub start_time: Time,
+    pub end_time: Time,
+    pub room: String,
+    /// Whether this is a lab session rather than a lecture
+    pub lab: bool,
+}
 
-        Ok(days
-            .map(|day| Period {
-                day,
-                start_time,
-                end_time,
-                room: room.to_string(),
+impl Period {
+    /// Parse a time slot like "MW 9:25AM-10:40AM" into one period per day
+    pub fn parse_periods(value: &str, room: &str) -> Result<Vec<Self>> {
+        let segments = time_slot::parse_time_slot(value)?;
+
+        Ok(segments
+            .into_iter()
+            .flat_map(|segment| {
+                segment.days.into_iter().map(move |day| Period {
+                    day,
+                    start_time: segment.start_time,
+                    end_time: segment.end_time,
+                    room: room.to_string(),
+                    lab: false,
+                })
             })
             .collect::<Vec<Period>>())
     }
@@ -156,18 +217,39 @@ mod tests {
             period,
             vec![
                 Period {
-                    day: 1,
+                    day: Weekday::Monday,
                     start_time: Time::new(9, 25),
                     end_time: Time::new(10, 40),
                     room: "Room 1".to_string(),
+                    lab: false,
                 },
                 Period {
-                    day: 3,
+                    day: Weekday::Wednesday,
                     start_time: Time::new(9, 25),
                     end_time: Time::new(10, 40),
                     room: "Room 1".to_string(),
+                    lab: false,
                 }
             ]
         );
     }
+
+    #[test]
+    fn invalid_day_letter_is_an_error() {
+        let err = Period::parse_periods("MX 9:25AM-10:40AM", "Room 1").unwrap_err();
+
+        assert!(matches!(
+            err,
+            TimetableError::TimeSlotSyntax { position: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn weekday_index_starts_from_sunday() {
+        for (index, day) in Weekday::ALL.iter().enumerate() {
+            assert_eq!(day.index() as usize, index);
+        }
+        assert_eq!(Weekday::from_letter('R').unwrap(), Weekday::Thursday);
+        assert_eq!(Weekday::from_letter('A').unwrap().two_letter(), "SA");
+    }
 }
@@ -0,0 +1,47 @@
+pub const DEFAULT_BASE_URL: &str = "https://portal.ewubd.edu";
+
+/// Location of the portal that all requests are sent to
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortalConfig {
+    /// Base URL without a trailing slash, e.g. "https://portal.ewubd.edu"
+    pub base_url: String,
+    pub origin: String,
+    pub referer: String,
+}
+
+impl PortalConfig {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        PortalConfig {
+            origin: base_url.clone(),
+            referer: format!("{}/", base_url),
+            base_url,
+        }
+    }
+
+    /// Builds an absolute URL from a path like "/api/utility/GetSemesterForDropDown"
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+impl Default for PortalConfig {
+    fn default() -> Self {
+        PortalConfig::new(DEFAULT_BASE_URL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_trims_trailing_slash() {
+        let config = PortalConfig::new("http://localhost:8080/");
+
+        assert_eq!(config.base_url, "http://localhost:8080");
+        assert_eq!(config.origin, "http://localhost:8080");
+        assert_eq!(config.referer, "http://localhost:8080/");
+        assert_eq!(config.url("/api/test"), "http://localhost:8080/api/test");
+    }
+}
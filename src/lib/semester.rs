@@ -1,9 +1,14 @@
 use chrono::{NaiveDate, NaiveDateTime};
 use reqwest::Client;
-use std::error::Error;
-use std::result::Result;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug)]
+use crate::{
+    error::{Result, TimetableError},
+    portal::PortalConfig,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Semester {
     pub id: u16,
     pub name: String,
@@ -11,43 +16,103 @@ pub struct Semester {
     pub end_date: NaiveDate,
 }
 
-pub async fn get_all_semesters(
-    client: &Client,
-) -> Result<Vec<Semester>, Box<dyn Error>> {
+/// A single semester as sent by `GetSemesterForDropDown`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SemesterRecord {
+    pub semester_id: u16,
+    pub semester_name: String,
+    pub starting_date: NaiveDateTime,
+    pub ending_date: NaiveDateTime,
+}
+
+impl From<SemesterRecord> for Semester {
+    fn from(record: SemesterRecord) -> Self {
+        Semester {
+            id: record.semester_id,
+            name: record.semester_name,
+            start_date: record.starting_date.date(),
+            end_date: record.ending_date.date(),
+        }
+    }
+}
+
+pub fn parse_semesters(semesters_json: serde_json::Value) -> Result<Vec<Semester>> {
+    let semesters_json_array = match semesters_json {
+        serde_json::Value::Array(array) => array,
+        _ => return Err(TimetableError::unexpected("semesters", "Expected an array")),
+    };
+
+    semesters_json_array
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| {
+            serde_path_to_error::deserialize::<_, SemesterRecord>(record)
+                .map(Semester::from)
+                .map_err(|e| {
+                    TimetableError::unexpected(
+                        format!("semesters[{}].{}", index, e.path()),
+                        e.inner().to_string(),
+                    )
+                })
+        })
+        .collect()
+}
+
+pub async fn get_all_semesters(client: &Client, config: &PortalConfig) -> Result<Vec<Semester>> {
     let res = client
-        .get("https://portal.ewubd.edu/api/utility/GetSemesterForDropDown")
+        .get(config.url("/api/utility/GetSemesterForDropDown"))
         .send()
         .await?
         .error_for_status()?;
 
     let json = res.json::<serde_json::Value>().await?;
 
-    let array = json.as_array().ok_or("Invalid semester JSON response")?;
-
-    let mut semesters = Vec::<Semester>::new();
-
-    for item in array {
-        let id = item["SemesterId"].as_u64().ok_or("Invalid semester ID")? as u16;
-        let name = item["SemesterName"]
-            .as_str()
-            .ok_or("Invalid semester name")?
-            .to_string();
-        let start_date = item["StartingDate"]
-            .as_str()
-            .ok_or("Invalid start date")
-            .map(|s| s.parse::<NaiveDateTime>())??;
-        let end_date = item["EndingDate"]
-            .as_str()
-            .ok_or("Invalid end date")
-            .map(|s| s.parse::<NaiveDateTime>())??;
-
-        semesters.push(Semester {
-            id,
-            name,
-            start_date: start_date.date(),
-            end_date: end_date.date(),
-        });
+    parse_semesters(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(id: u64) -> serde_json::Value {
+        json!({
+            "SemesterId": id,
+            "SemesterName": "Fall-2024",
+            "StartingDate": "2024-09-01T00:00:00",
+            "EndingDate": "2024-12-19T00:00:00"
+        })
     }
 
-    Ok(semesters)
+    #[test]
+    fn parses_semester_record() {
+        assert_eq!(
+            parse_semesters(json!([record(1)])).unwrap(),
+            vec![Semester {
+                id: 1,
+                name: "Fall-2024".to_string(),
+                start_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2024, 12, 19).unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn malformed_record_names_field() {
+        // IDs that don't fit are rejected rather than truncated
+        let err = parse_semesters(json!([record(1), record(70_000)])).unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::UnexpectedPortalResponse { ref field, .. } if field == "semesters[1].SemesterId"
+        ));
+
+        let mut bad = record(1);
+        bad["EndingDate"] = json!("19/12/2024");
+        let err = parse_semesters(json!([bad])).unwrap_err();
+        assert!(matches!(
+            err,
+            TimetableError::UnexpectedPortalResponse { ref field, .. } if field == "semesters[0].EndingDate"
+        ));
+    }
 }
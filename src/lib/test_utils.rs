@@ -0,0 +1,20 @@
+//! Fixtures shared by the unit tests.
+
+use chrono::NaiveDate;
+
+use crate::periods::{Period, Time, Weekday};
+
+pub fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+/// A lecture period, with `start` and `end` given as (hour, minute).
+pub fn period(day: Weekday, start: (u8, u8), end: (u8, u8), room: &str) -> Period {
+    Period {
+        day,
+        start_time: Time::new(start.0, start.1),
+        end_time: Time::new(end.0, end.1),
+        room: room.to_string(),
+        lab: false,
+    }
+}
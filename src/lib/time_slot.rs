@@ -0,0 +1,332 @@
+//! Parser for the `TimeSlotName` strings sent by the portal.
+//!
+//! ```text
+//! slot     = segment { [","|";"|"/"|"&"] segment }
+//! segment  = days range
+//! days     = day-letter { day-letter }        ; S M T W R F A, any case
+//! range    = time ("-"|"–") time
+//! time     = hour [(":"|".") minute] meridiem  ; "9AM", "9:25 am", "09.25P.M."
+//! meridiem = ("A"|"P") ["."] "M" ["."]
+//! ```
+//!
+//! Whitespace is allowed between all tokens and segments.
+
+use crate::{
+    error::{Result, TimetableError},
+    periods::{Time, Weekday},
+};
+
+/// One "<days> <start>-<end>" part of a time slot
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSlotSegment {
+    pub days: Vec<Weekday>,
+    pub start_time: Time,
+    pub end_time: Time,
+}
+
+const SEGMENT_SEPARATORS: [char; 4] = [',', ';', '/', '&'];
+const RANGE_SEPARATORS: [char; 2] = ['-', '–'];
+
+struct Parser<'a> {
+    input: &'a str,
+    /// Byte offset into `input`
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Parser { input, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, pred: impl Fn(char) -> bool) -> Option<char> {
+        match self.peek() {
+            Some(c) if pred(c) => self.bump(),
+            _ => None,
+        }
+    }
+
+    /// Skips whitespace, returns whether any was skipped
+    fn skip_ws(&mut self) -> bool {
+        let start = self.pos;
+        while self.eat(char::is_whitespace).is_some() {}
+        self.pos != start
+    }
+
+    fn is_eof(&self) -> bool {
+        self.pos == self.input.len()
+    }
+
+    fn error_at(&self, pos: usize, expected: &str) -> TimetableError {
+        TimetableError::TimeSlotSyntax {
+            input: self.input.to_string(),
+            position: self.input[..pos].chars().count(),
+            expected: expected.to_string(),
+        }
+    }
+
+    fn error(&self, expected: &str) -> TimetableError {
+        self.error_at(self.pos, expected)
+    }
+
+    fn slot(&mut self) -> Result<Vec<TimeSlotSegment>> {
+        let mut segments = Vec::new();
+
+        self.skip_ws();
+        loop {
+            segments.push(self.segment()?);
+
+            let had_ws = self.skip_ws();
+            if self.is_eof() {
+                break;
+            }
+            if self.eat(|c| SEGMENT_SEPARATORS.contains(&c)).is_some() {
+                self.skip_ws();
+            } else if !had_ws {
+                return Err(self.error("a separator or the end of the time slot"));
+            }
+        }
+
+        Ok(segments)
+    }
+
+    fn segment(&mut self) -> Result<TimeSlotSegment> {
+        let days = self.days()?;
+        self.skip_ws();
+
+        let start_time = self.time()?;
+        self.skip_ws();
+        if self.eat(|c| RANGE_SEPARATORS.contains(&c)).is_none() {
+            return Err(self.error("'-' between start and end time"));
+        }
+        self.skip_ws();
+
+        let end_time_pos = self.pos;
+        let end_time = self.time()?;
+        if (end_time.hours, end_time.minutes) <= (start_time.hours, start_time.minutes) {
+            return Err(self.error_at(end_time_pos, "an end time after the start time"));
+        }
+
+        Ok(TimeSlotSegment {
+            days,
+            start_time,
+            end_time,
+        })
+    }
+
+    fn days(&mut self) -> Result<Vec<Weekday>> {
+        let mut days = Vec::new();
+
+        while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+            let day = Weekday::from_letter(c.to_ascii_uppercase())
+                .map_err(|_| self.error("a day letter (S, M, T, W, R, F or A)"))?;
+            if !days.contains(&day) {
+                days.push(day);
+            }
+            self.bump();
+        }
+
+        if days.is_empty() {
+            return Err(self.error("a day letter (S, M, T, W, R, F or A)"));
+        }
+
+        Ok(days)
+    }
+
+    fn number(&mut self, min_digits: usize, max_digits: usize, expected: &str) -> Result<u8> {
+        let start = self.pos;
+        while self.pos - start < max_digits && self.eat(|c| c.is_ascii_digit()).is_some() {}
+
+        if self.pos - start < min_digits {
+            return Err(self.error_at(start, expected));
+        }
+        self.input[start..self.pos]
+            .parse()
+            .map_err(|_| self.error_at(start, expected))
+    }
+
+    fn time(&mut self) -> Result<Time> {
+        let hours_pos = self.pos;
+        let hours = self.number(1, 2, "an hour")?;
+        if !(1..=12).contains(&hours) {
+            return Err(self.error_at(hours_pos, "an hour between 1 and 12"));
+        }
+
+        let minutes = if self.eat(|c| c == ':' || c == '.').is_some() {
+            let minutes_pos = self.pos;
+            let minutes = self.number(2, 2, "two digit minutes")?;
+            if minutes >= 60 {
+                return Err(self.error_at(minutes_pos, "minutes between 00 and 59"));
+            }
+            minutes
+        } else {
+            0
+        };
+
+        self.skip_ws();
+        let pm = self.meridiem()?;
+
+        let hours = match (hours, pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (hours, false) => hours,
+            (hours, true) => hours + 12,
+        };
+
+        Ok(Time::new(hours, minutes))
+    }
+
+    /// Returns true for PM
+    fn meridiem(&mut self) -> Result<bool> {
+        let pos = self.pos;
+        let pm = match self.bump().map(|c| c.to_ascii_uppercase()) {
+            Some('A') => false,
+            Some('P') => true,
+            _ => return Err(self.error_at(pos, "AM or PM")),
+        };
+        self.eat(|c| c == '.');
+        if self.eat(|c| c.eq_ignore_ascii_case(&'M')).is_none() {
+            return Err(self.error_at(pos, "AM or PM"));
+        }
+        self.eat(|c| c == '.');
+
+        Ok(pm)
+    }
+}
+
+/// Parse a full time slot such as "MW 8:30AM-10:00AM, R 10:10AM-12:10PM"
+pub fn parse_time_slot(input: &str) -> Result<Vec<TimeSlotSegment>> {
+    Parser::new(input).slot()
+}
+
+/// Parse a single 12-hr time such as "9:25AM" or "9 pm"
+pub fn parse_time(input: &str) -> Result<Time> {
+    let mut parser = Parser::new(input);
+    parser.skip_ws();
+    let time = parser.time()?;
+    parser.skip_ws();
+    if !parser.is_eof() {
+        return Err(parser.error("the end of the time"));
+    }
+    Ok(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Weekday::*;
+
+    fn segment(days: &[Weekday], start: (u8, u8), end: (u8, u8)) -> TimeSlotSegment {
+        TimeSlotSegment {
+            days: days.to_vec(),
+            start_time: Time::new(start.0, start.1),
+            end_time: Time::new(end.0, end.1),
+        }
+    }
+
+    #[test]
+    fn parses_time_slots() {
+        let cases = [
+            (
+                "MW 9:25AM-10:40AM",
+                vec![segment(&[Monday, Wednesday], (9, 25), (10, 40))],
+            ),
+            (
+                "ST 1:30PM-3:00PM",
+                vec![segment(&[Sunday, Tuesday], (13, 30), (15, 0))],
+            ),
+            (
+                "R 10:10AM-12:10PM",
+                vec![segment(&[Thursday], (10, 10), (12, 10))],
+            ),
+            (
+                "A 8:00AM-11:00AM",
+                vec![segment(&[Saturday], (8, 0), (11, 0))],
+            ),
+            (
+                "  MW   8:30AM - 10:00AM  ",
+                vec![segment(&[Monday, Wednesday], (8, 30), (10, 0))],
+            ),
+            (
+                "mw 8:30am-10:00am",
+                vec![segment(&[Monday, Wednesday], (8, 30), (10, 0))],
+            ),
+            (
+                "TR 3:10 PM-4:40 PM",
+                vec![segment(&[Tuesday, Thursday], (15, 10), (16, 40))],
+            ),
+            ("F 9AM-11AM", vec![segment(&[Friday], (9, 0), (11, 0))]),
+            (
+                "S 11:50AM–1:20P.M.",
+                vec![segment(&[Sunday], (11, 50), (13, 20))],
+            ),
+            (
+                "MW 8:30AM-10:00AM R 10:10AM-12:10PM",
+                vec![
+                    segment(&[Monday, Wednesday], (8, 30), (10, 0)),
+                    segment(&[Thursday], (10, 10), (12, 10)),
+                ],
+            ),
+            (
+                "MW 8:30AM-10:00AM, T 1:30PM-3:30PM; R 4PM-6PM",
+                vec![
+                    segment(&[Monday, Wednesday], (8, 30), (10, 0)),
+                    segment(&[Tuesday], (13, 30), (15, 30)),
+                    segment(&[Thursday], (16, 0), (18, 0)),
+                ],
+            ),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(parse_time_slot(input).unwrap(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let cases = [
+            ("", 0, "a day letter"),
+            ("   ", 3, "a day letter"),
+            ("MX 9:25AM-10:40AM", 1, "a day letter"),
+            ("MW", 2, "an hour"),
+            ("MW 9:25-10:40AM", 7, "AM or PM"),
+            ("MW 9:25AM 10:40AM", 10, "'-'"),
+            ("MW 13:00PM-2:00PM", 3, "an hour between 1 and 12"),
+            ("MW 9:2AM-10:40AM", 5, "two digit minutes"),
+            ("MW 9:75AM-10:40AM", 5, "minutes between"),
+            ("MW 10:00AM-9:00AM", 11, "an end time after"),
+            ("MW 9:25AM-10:40AMR 1:00PM-2:00PM", 17, "a separator"),
+            ("MW 9:25AM-10:40AM,", 18, "a day letter"),
+        ];
+
+        for (input, position, expected) in cases {
+            match parse_time_slot(input).unwrap_err() {
+                TimetableError::TimeSlotSyntax {
+                    position: p,
+                    expected: e,
+                    ..
+                } => {
+                    assert_eq!(p, position, "{}", input);
+                    assert!(e.starts_with(expected), "{}: {}", input, e);
+                }
+                err => panic!("unexpected error {:?} for {}", err, input),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_single_time() {
+        assert_eq!(parse_time("12:00AM").unwrap(), Time::new(0, 0));
+        assert_eq!(parse_time(" 9 pm ").unwrap(), Time::new(21, 0));
+        assert!(parse_time("9:00AM-10:00AM").is_err());
+    }
+}
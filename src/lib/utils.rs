@@ -1,27 +1,37 @@
+use crate::{
+    error::{Result, TimetableError},
+    portal::PortalConfig,
+};
 use reqwest::header;
-use std::error::Error;
 
 pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3";
 
-pub fn build_headers(session_id: &str) -> header::HeaderMap {
+/// Headers sent with every portal request. A session that can't be sent as a
+/// header, such as a bearer token with a line break, can't be a valid one.
+pub fn build_headers(config: &PortalConfig, session_id: &str) -> Result<header::HeaderMap> {
     use header::{HeaderMap, HeaderValue};
 
+    let portal_url = |value: &str| {
+        HeaderValue::from_str(value)
+            .map_err(|_| TimetableError::InvalidPortalUrl(value.to_string()))
+    };
+
     let mut headers = HeaderMap::new();
     headers.insert(header::USER_AGENT, HeaderValue::from_static(USER_AGENT));
-    headers.insert(header::COOKIE, HeaderValue::from_str(session_id).unwrap());
-    headers.insert(
-        header::ORIGIN,
-        HeaderValue::from_str("https://portal.ewubd.edu").unwrap(),
-    );
     headers.insert(
-        header::REFERER,
-        HeaderValue::from_str("https://portal.ewubd.edu/").unwrap(),
+        header::COOKIE,
+        HeaderValue::from_str(session_id).map_err(|_| TimetableError::SessionExpired)?,
     );
-    headers
+    headers.insert(header::ORIGIN, portal_url(&config.origin)?);
+    headers.insert(header::REFERER, portal_url(&config.referer)?);
+    Ok(headers)
 }
 
-pub fn build_authenticated_client(session_id: &str) -> Result<reqwest::Client, Box<dyn Error>> {
-    let headers = build_headers(session_id);
+pub fn build_authenticated_client(
+    config: &PortalConfig,
+    session_id: &str,
+) -> Result<reqwest::Client> {
+    let headers = build_headers(config, session_id)?;
     let client = reqwest::Client::builder()
         .use_rustls_tls()
         .default_headers(headers)
@@ -29,16 +39,21 @@ pub fn build_authenticated_client(session_id: &str) -> Result<reqwest::Client, B
     Ok(client)
 }
 
-pub fn get_session_cookie(req: &actix_web::HttpRequest) -> Result<String, actix_web::error::Error> {
-    let cookies = req.cookies();
-    let cookie = cookies
-        .iter()
-        .find_map(|c| c.iter().find(|c| c.name() == "ASP.NET_SessionId"));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    match cookie {
-        Some(cookie) => Ok(cookie.to_string()),
-        None => Err(actix_web::error::ErrorUnauthorized(
-            "Session cookie not found",
-        )),
+    #[test]
+    fn rejects_values_that_cannot_be_headers() {
+        let config = PortalConfig::default();
+        assert!(build_authenticated_client(&config, "ASP.NET_SessionId=abc").is_ok());
+        assert!(matches!(
+            build_authenticated_client(&config, "ASP.NET_SessionId=abc\r\nX-Evil: 1"),
+            Err(TimetableError::SessionExpired)
+        ));
+        assert!(matches!(
+            build_authenticated_client(&PortalConfig::new("https://portal\n.example"), "a=b"),
+            Err(TimetableError::InvalidPortalUrl(_))
+        ));
     }
 }
@@ -0,0 +1,273 @@
+use std::{io, time::Duration};
+
+use ewubd_timetable_calendar_lib::portal;
+use serde::{Deserialize, Deserializer};
+
+use crate::subscription;
+
+/// Environment variable naming the TOML config file
+pub const CONFIG_FILE_VAR: &str = "EWU_CONFIG";
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+/// Server settings, read from the TOML file named by `EWU_CONFIG` and
+/// overridden by `EWU_*` environment variables. Durations are in seconds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_address: String,
+    pub port: u16,
+    /// Number of worker threads, defaults to one per CPU core
+    pub workers: Option<usize>,
+    /// How long generated calendars stay available for download
+    #[serde(deserialize_with = "seconds")]
+    pub calendar_ttl: Duration,
+    /// Lifetime of the session cookie set on login
+    #[serde(deserialize_with = "seconds")]
+    pub session_ttl: Duration,
+    /// Subscriptions that no calendar app has polled for this long are
+    /// deleted along with their stored credentials
+    #[serde(deserialize_with = "seconds")]
+    pub subscription_idle: Duration,
+    /// Secret of at least 32 bytes the session cookie and the credentials stored
+    /// with subscriptions are encrypted with. A random one is generated when
+    /// unset, which logs everyone out and stops subscriptions from logging in
+    /// again on restart.
+    pub session_key: Option<String>,
+    /// Where calendars are stored, "memory", "file:<dir>" or "sqlite:<path>"
+    pub calendar_store: String,
+    /// Base URL of the portal that logins and timetable requests are sent to
+    pub portal_url: String,
+    /// URL the server is reached at from outside, such as "https://timetable.example.com",
+    /// used in subscription links. Defaults to the Host of each request.
+    pub public_url: Option<String>,
+    /// env_logger filter such as "info" or "ewubd_timetable_calendar=debug"
+    pub log_level: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_address: "0.0.0.0".to_string(),
+            port: 3000,
+            workers: None,
+            calendar_ttl: Duration::from_secs(900),
+            session_ttl: Duration::from_secs(900),
+            subscription_idle: Duration::from_secs(30 * 24 * 60 * 60),
+            session_key: None,
+            calendar_store: "memory".to_string(),
+            portal_url: portal::DEFAULT_BASE_URL.to_string(),
+            public_url: None,
+            log_level: "info".to_string(),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, value: &str, expected: &str) -> io::Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| invalid(format!("{} must be {}, got \"{}\"", name, expected, value)))
+}
+
+impl Config {
+    /// Parses a TOML config file, unset keys keep their defaults
+    pub fn from_toml(text: &str) -> io::Result<Self> {
+        toml::from_str(text).map_err(|e| invalid(format!("Invalid config file: {}", e)))
+    }
+
+    /// Overrides settings with the `EWU_*` variables `var` returns
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> io::Result<()> {
+        if let Some(value) = var("EWU_BIND_ADDRESS") {
+            self.bind_address = value;
+        }
+        if let Some(value) = var("EWU_PORT") {
+            self.port = parse_env("EWU_PORT", &value, "a port number")?;
+        }
+        if let Some(value) = var("EWU_WORKERS") {
+            self.workers = Some(parse_env("EWU_WORKERS", &value, "a number of threads")?);
+        }
+        if let Some(value) = var("EWU_CALENDAR_TTL") {
+            let secs = parse_env("EWU_CALENDAR_TTL", &value, "a number of seconds")?;
+            self.calendar_ttl = Duration::from_secs(secs);
+        }
+        if let Some(value) = var("EWU_SESSION_TTL") {
+            let secs = parse_env("EWU_SESSION_TTL", &value, "a number of seconds")?;
+            self.session_ttl = Duration::from_secs(secs);
+        }
+        if let Some(value) = var("EWU_SUBSCRIPTION_IDLE") {
+            let secs = parse_env("EWU_SUBSCRIPTION_IDLE", &value, "a number of seconds")?;
+            self.subscription_idle = Duration::from_secs(secs);
+        }
+        if let Some(value) = var("EWU_SESSION_KEY") {
+            self.session_key = Some(value).filter(|key| !key.is_empty());
+        }
+        if let Some(value) = var("EWU_CALENDAR_STORE") {
+            self.calendar_store = value;
+        }
+        if let Some(value) = var("EWU_PORTAL_URL") {
+            self.portal_url = value;
+        }
+        if let Some(value) = var("EWU_PUBLIC_URL") {
+            self.public_url = Some(value).filter(|url| !url.is_empty());
+        }
+        if let Some(value) = var("EWU_LOG") {
+            self.log_level = value;
+        }
+        Ok(())
+    }
+
+    fn validate(self) -> io::Result<Self> {
+        if self.workers == Some(0) {
+            return Err(invalid("workers must be at least 1".to_string()));
+        }
+        if self.calendar_ttl.is_zero() {
+            return Err(invalid(
+                "calendar_ttl must be at least 1 second".to_string(),
+            ));
+        }
+        if self.session_ttl.is_zero() {
+            return Err(invalid("session_ttl must be at least 1 second".to_string()));
+        }
+        if self.subscription_idle <= subscription::REFRESH_INTERVAL {
+            return Err(invalid(format!(
+                "subscription_idle must be longer than {} seconds, the refresh interval",
+                subscription::REFRESH_INTERVAL.as_secs()
+            )));
+        }
+        if self.session_key.as_ref().is_some_and(|key| key.len() < 32) {
+            return Err(invalid(
+                "session_key must be at least 32 bytes long".to_string(),
+            ));
+        }
+        if let Some(url) = &self.public_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(invalid(format!(
+                    "public_url must start with http:// or https://, got \"{}\"",
+                    url
+                )));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Reads the config file named by `EWU_CONFIG`, if set, then applies the
+    /// environment overrides
+    pub fn load() -> io::Result<Self> {
+        let mut config = match std::env::var(CONFIG_FILE_VAR) {
+            Ok(path) => Config::from_toml(&std::fs::read_to_string(&path).map_err(|e| {
+                io::Error::new(e.kind(), format!("Cannot read config file {}: {}", path, e))
+            })?)?,
+            Err(_) => Config::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()
+    }
+
+    /// Whether the session cookie is marked Secure. It is unless the public URL
+    /// is plain http://, as when trying the server out locally, since browsers
+    /// don't send Secure cookies over plain HTTP.
+    pub fn secure_cookies(&self) -> bool {
+        !self
+            .public_url
+            .as_deref()
+            .is_some_and(|url| url.starts_with("http://"))
+    }
+
+    /// Host and path prefix of the public URL without the scheme, as used in
+    /// webcal:// links
+    pub fn public_host(&self) -> Option<&str> {
+        self.public_url.as_deref().map(|url| {
+            url.split_once("://")
+                .map_or(url, |(_, host)| host)
+                .trim_end_matches('/')
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_then_env() {
+        let mut config = Config::from_toml(
+            r#"
+            port = 8080
+            workers = 2
+            calendar_ttl = 3600
+            public_url = "https://timetable.example.com/"
+            "#,
+        )
+        .unwrap();
+        config
+            .apply_env(|name| match name {
+                "EWU_PORT" => Some("9000".to_string()),
+                "EWU_LOG" => Some("debug".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        let config = config.validate().unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                port: 9000,
+                workers: Some(2),
+                calendar_ttl: Duration::from_secs(3600),
+                public_url: Some("https://timetable.example.com/".to_string()),
+                log_level: "debug".to_string(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(config.public_host(), Some("timetable.example.com"));
+        assert!(config.secure_cookies());
+        assert!(Config::default().secure_cookies());
+        assert!(!Config::from_toml("public_url = \"http://localhost:3000\"")
+            .unwrap()
+            .secure_cookies());
+    }
+
+    #[test]
+    fn example_file_has_the_defaults() {
+        assert_eq!(
+            Config::from_toml(include_str!("../../config.example.toml")).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(Config::from_toml("prot = 8080").is_err());
+        assert!(Config::from_toml("port = \"eighty\"").is_err());
+        assert!(Config::default()
+            .apply_env(|name| (name == "EWU_SESSION_TTL").then(|| "15m".to_string()))
+            .is_err());
+        assert!(Config::from_toml("subscription_idle = 3600")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(Config::from_toml("workers = 0")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(Config::from_toml("session_ttl = 0")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(Config::from_toml("session_key = \"secret\"")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(Config::from_toml("public_url = \"timetable.example.com\"")
+            .unwrap()
+            .validate()
+            .is_err());
+    }
+}
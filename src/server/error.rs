@@ -0,0 +1,135 @@
+use std::fmt::Display;
+
+use actix_web::{error, http::StatusCode, HttpResponse, ResponseError};
+use ewubd_timetable_calendar_lib::error::TimetableError;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Maps a library error to the HTTP status and message shown to the user
+pub fn to_http_error(err: TimetableError) -> error::Error {
+    match err {
+        TimetableError::InvalidCredentials(msg) => error::ErrorUnauthorized(msg),
+        TimetableError::SessionExpired => error::ErrorUnauthorized("Session expired"),
+        TimetableError::PortalUnavailable(_) => {
+            error::ErrorBadGateway("The portal is unavailable, please try again later")
+        }
+        TimetableError::InvalidPortalUrl(_) => {
+            error::ErrorInternalServerError("The server's portal URL is misconfigured")
+        }
+        TimetableError::UnexpectedPortalResponse { field, .. } => error::ErrorBadGateway(format!(
+            "The portal sent an unexpected response ({})",
+            field
+        )),
+        TimetableError::InvalidTimeSlot(slot) => error::ErrorUnprocessableEntity(format!(
+            "Could not understand the time slot \"{}\"",
+            slot
+        )),
+        err @ (TimetableError::TimeSlotSyntax { .. }
+        | TimetableError::InvalidHoliday { .. }
+        | TimetableError::InvalidOverride { .. }
+        | TimetableError::UnmatchedOverride(_)
+        | TimetableError::InvalidExam { .. }
+        | TimetableError::InvalidCalendar { .. }) => {
+            error::ErrorUnprocessableEntity(err.to_string())
+        }
+    }
+}
+
+/// Body of every error response of the JSON API
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody<'a> {
+    pub error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorDetail<'a> {
+    /// Stable machine-readable code such as "session_expired"
+    pub code: &'a str,
+    /// Explanation for humans, may change at any time
+    pub message: &'a str,
+}
+
+/// Error returned by the JSON API as an [`ErrorBody`]. Codes are part of the
+/// API and must not change once released.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(ErrorBody {
+            error: ErrorDetail {
+                code: self.code,
+                message: &self.message,
+            },
+        })
+    }
+}
+
+impl From<TimetableError> for ApiError {
+    fn from(err: TimetableError) -> Self {
+        let (status, code) = match &err {
+            TimetableError::InvalidCredentials(_) => {
+                (StatusCode::UNAUTHORIZED, "invalid_credentials")
+            }
+            TimetableError::SessionExpired => (StatusCode::UNAUTHORIZED, "session_expired"),
+            TimetableError::PortalUnavailable(_) => (StatusCode::BAD_GATEWAY, "portal_unavailable"),
+            TimetableError::InvalidPortalUrl(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+            TimetableError::UnexpectedPortalResponse { .. } => {
+                (StatusCode::BAD_GATEWAY, "unexpected_portal_response")
+            }
+            TimetableError::InvalidTimeSlot(_) | TimetableError::TimeSlotSyntax { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "invalid_time_slot")
+            }
+            TimetableError::InvalidHoliday { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "invalid_holiday")
+            }
+            TimetableError::InvalidOverride { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "invalid_override")
+            }
+            TimetableError::UnmatchedOverride(_) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "unmatched_override")
+            }
+            TimetableError::InvalidExam { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "invalid_exam")
+            }
+            TimetableError::InvalidCalendar { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "invalid_calendar")
+            }
+        };
+        ApiError::new(status, code, err.to_string())
+    }
+}
+
+impl From<error::BlockingError> for ApiError {
+    fn from(err: error::BlockingError) -> Self {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            err.to_string(),
+        )
+    }
+}
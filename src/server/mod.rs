@@ -1,114 +1,160 @@
-use std::sync::Mutex;
+use std::{sync::Arc, time::Duration};
 
 use actix_web::{
-    body::{BoxBody, MessageBody},
+    body::BoxBody,
+    cookie::Key,
     dev::{ServiceRequest, ServiceResponse},
     http::{
         header::{self, HeaderValue},
         StatusCode,
     },
     middleware::{from_fn, Logger, Next},
-    web::Data,
+    web::{self, Data},
     App, HttpServer,
 };
 use env_logger::Env;
+use ewubd_timetable_calendar_lib::portal::PortalConfig;
 
+mod config;
+mod error;
 mod partials;
 mod routes;
+mod session;
+mod store;
+mod subscription;
+#[cfg(test)]
+mod tests;
 
+use config::Config;
 use maud::html;
-use partials::page;
+use partials::{login_form, page};
+use store::CalendarStore;
 
-#[derive(Debug, Hash, PartialEq)]
-pub struct CalendarEntry {
-    pub created_at: std::time::Instant,
-    pub ical: String,
-}
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
 
 struct AppState {
-    /// Stores a hashmap of temporary generated calendars
-    calendars: Mutex<std::collections::HashMap<String, CalendarEntry>>,
+    /// Stores temporary generated calendars
+    calendars: Arc<dyn CalendarStore>,
+    /// How long generated calendars stay available
+    calendar_ttl: Duration,
+    /// Portal that logins and timetable requests are sent to
+    portal: PortalConfig,
+    /// Lifetime of the session cookie set on login
+    session_ttl: Duration,
+    /// Key the session cookie and stored subscription credentials are encrypted with
+    session_key: Key,
+    /// Whether the session cookie is only sent over HTTPS, see [`Config::secure_cookies`]
+    secure_cookies: bool,
+    /// Host used in subscription links instead of the request's, see [`Config::public_host`]
+    public_host: Option<String>,
 }
 
-/// Middleware to delete calendars that are older than 15 minutes
-async fn cleanup_calendars(
-    req: ServiceRequest,
-    next: Next<impl MessageBody>,
-) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
-    {
-        let state =
-            req.app_data::<Data<AppState>>()
-                .ok_or(actix_web::error::ErrorInternalServerError(
-                    "Cannot access app state",
-                ))?;
-
-        let mut calendars = state
-            .calendars
-            .lock()
-            .map_err(|_| actix_web::error::ErrorInternalServerError("Cannot access calendars"))?;
-        calendars.retain(|_, entry| entry.created_at.elapsed().as_secs() < 900);
-    }
+/// Periodically deletes calendars that are older than `ttl` and subscriptions
+/// that have been idle for `idle`
+async fn cleanup_calendars(calendars: Arc<dyn CalendarStore>, ttl: Duration, idle: Duration) {
+    let mut interval = actix_web::rt::time::interval(CLEANUP_INTERVAL.min(ttl));
+
+    loop {
+        interval.tick().await;
+
+        let expired = calendars.clone();
+        match web::block(move || expired.remove_expired(ttl)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(removed)) => log::info!("Removed {} expired calendars", removed),
+            Ok(Err(e)) => log::error!("Cannot remove expired calendars: {}", e),
+            Err(e) => log::error!("Cannot remove expired calendars: {}", e),
+        }
 
-    next.call(req).await
+        let subscriptions = calendars.clone();
+        match web::block(move || subscriptions.remove_idle_subscriptions(idle)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(removed)) => log::info!("Removed {} idle subscriptions", removed),
+            Ok(Err(e)) => log::error!("Cannot remove idle subscriptions: {}", e),
+            Err(e) => log::error!("Cannot remove idle subscriptions: {}", e),
+        }
+    }
 }
 
 async fn auth_middleware(
     req: ServiceRequest,
     next: Next<BoxBody>,
 ) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
-    let res = next.call(req).await;
-
-    res.map(|mut res| {
-        if res.status() == StatusCode::UNAUTHORIZED {
-            let login_page = page(
-                "Login",
-                false,
-                html! {
-                    p {
-                        mark { "Please login again. Possible errors: session expired, invalid credentials, or login blocked." };
-                    }
-                    form action="/" method="post" {
-                        input type="text" name="username" placeholder="Student ID";
-                        input type="password" name="password" placeholder="Password";
-                        input type="submit" value="Login";
-                    }
-                },
-            );
+    let mut res = next.call(req).await?;
 
-            res.headers_mut()
-                .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html"));
-            res.map_body(|_, _| BoxBody::new(login_page.into_string()))
-        } else {
-            res.map_into_boxed_body()
-        }
-    })
+    // the JSON API reports its own errors
+    if res.status() == StatusCode::UNAUTHORIZED && !res.request().path().starts_with("/api/") {
+        let secure = res
+            .request()
+            .app_data::<Data<AppState>>()
+            .is_none_or(|state| state.secure_cookies);
+        let (csrf_token, cookie) = session::login_csrf(secure);
+        let login_page = page(
+            "Login",
+            None,
+            html! {
+                p {
+                    mark { "Please login again. Possible errors: session expired, invalid credentials, or login blocked." };
+                }
+                (login_form(&csrf_token))
+            },
+        );
+
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        res.response_mut().add_cookie(&cookie)?;
+        Ok(res.map_body(|_, _| BoxBody::new(login_page.into_string())))
+    } else {
+        Ok(res.map_into_boxed_body())
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    use routes::*;
+    let config = Config::load()?;
+    env_logger::init_from_env(Env::default().default_filter_or(&config.log_level));
+
+    let calendars: Arc<dyn CalendarStore> = store::from_spec(&config.calendar_store)?.into();
 
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    actix_web::rt::spawn(cleanup_calendars(
+        calendars.clone(),
+        config.calendar_ttl,
+        config.subscription_idle,
+    ));
+
+    let session_key = match &config.session_key {
+        Some(secret) => Key::derive_from(secret.as_bytes()),
+        None => {
+            log::warn!(
+                "No session_key configured, sessions and stored subscription logins won't survive a restart"
+            );
+            Key::generate()
+        }
+    };
 
     let app_data = Data::new(AppState {
-        calendars: Mutex::new(std::collections::HashMap::new()),
+        calendars,
+        calendar_ttl: config.calendar_ttl,
+        portal: PortalConfig::new(&config.portal_url),
+        session_ttl: config.session_ttl,
+        session_key,
+        secure_cookies: config.secure_cookies(),
+        public_host: config.public_host().map(str::to_string),
     });
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(app_data.clone())
             .wrap(from_fn(auth_middleware))
-            .wrap(from_fn(cleanup_calendars))
-            .service(index::index)
-            .service(index::login)
-            .service(dashboard::dashboard)
-            .service(dashboard::timetable)
-            .service(dashboard::generate)
-            .service(dashboard::download)
-            .service(logout::logout)
+            .configure(routes::configure)
             .wrap(Logger::default())
-    })
-    .bind(("0.0.0.0", 3000))?
-    .run()
-    .await
+    });
+    if let Some(workers) = config.workers {
+        server = server.workers(workers);
+    }
+
+    log::info!("Listening on {}:{}", config.bind_address, config.port);
+    server
+        .bind((config.bind_address.as_str(), config.port))?
+        .run()
+        .await
 }
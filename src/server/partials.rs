@@ -1,5 +1,13 @@
+use ewubd_timetable_calendar_lib::{
+    conflicts::Conflict,
+    courses::Course,
+    periods::{Time, Weekday},
+};
 use maud::{html, Markup, DOCTYPE};
 
+/// Minutes covered by each row of the week grid
+const GRID_STEP: u16 = 5;
+
 pub fn header(title: &str) -> Markup {
     html! {
         h1 {
@@ -8,7 +16,9 @@ pub fn header(title: &str) -> Markup {
     }
 }
 
-pub fn page(title: &str, logout: bool, body: Markup) -> Markup {
+/// A full page. Pages shown to a logged-in student pass their session's CSRF
+/// token, which the logout button posts.
+pub fn page(title: &str, csrf_token: Option<&str>, body: Markup) -> Markup {
     html! {
         (DOCTYPE)
         html {
@@ -26,8 +36,13 @@ pub fn page(title: &str, logout: bool, body: Markup) -> Markup {
                         }
                         ul {
                             li { a href="https://github.com/arafatamim/ewubd-timetable" { "Source code" } }
-                            @if logout {
-                                li { a href="/logout" { "Logout" } }
+                            @if let Some(csrf_token) = csrf_token {
+                                li {
+                                    form action="/logout" method="post" {
+                                        input type="hidden" name="csrf_token" value=(csrf_token);
+                                        input type="submit" value="Logout";
+                                    }
+                                }
                             }
                         }
                     }
@@ -40,3 +55,86 @@ pub fn page(title: &str, logout: bool, body: Markup) -> Markup {
         }
     }
 }
+
+/// Form that logs in through the portal, posting the token from
+/// [`crate::session::login_csrf`]
+pub fn login_form(csrf_token: &str) -> Markup {
+    html! {
+        form action="/" method="post" {
+            input type="hidden" name="csrf_token" value=(csrf_token);
+            input type="text" name="username" placeholder="Student ID" required;
+            br;
+            input type="password" name="password" placeholder="Password" required;
+            br;
+            input type="submit" value="Login";
+        }
+    }
+}
+
+/// Week of classes laid out with days as columns and time as rows, each course
+/// in its own colour. Conflicting classes are outlined.
+pub fn week_grid(courses: &[Course], conflicts: &[Conflict]) -> Markup {
+    let classes = courses
+        .iter()
+        .enumerate()
+        .flat_map(|(index, course)| course.periods.iter().map(move |p| (index, course, p)))
+        .collect::<Vec<_>>();
+
+    let first_hour = classes
+        .iter()
+        .map(|(_, _, period)| period.start_time.hours as u16)
+        .min()
+        .unwrap_or(8);
+    let last_hour = classes
+        .iter()
+        .map(|(_, _, period)| period.end_time.minutes().div_ceil(60))
+        .max()
+        .unwrap_or(17)
+        .max(first_hour + 1);
+    // row 1 holds the day names
+    let row = |minutes: u16| (minutes - first_hour * 60) / GRID_STEP + 2;
+    let rows_per_hour = 60 / GRID_STEP;
+
+    html! {
+        div style="overflow-x: auto" {
+            div style=(format!(
+                "display: grid; min-width: 48rem; grid-template-columns: 5rem repeat(7, 1fr); \
+                 grid-template-rows: auto repeat({}, 0.45rem); column-gap: 2px",
+                (last_hour - first_hour) * rows_per_hour
+            )) {
+                @for day in Weekday::ALL {
+                    div style=(format!("grid-column: {}; grid-row: 1; text-align: center", day.index() + 2)) {
+                        strong { (day) }
+                    }
+                }
+                @for hour in first_hour..last_hour {
+                    div style=(format!(
+                        "grid-column: 1 / -1; grid-row: {} / span {}; border-top: 1px solid #8884",
+                        row(hour * 60),
+                        rows_per_hour
+                    )) {
+                        small { (Time::new(hour as u8, 0)) }
+                    }
+                }
+                @for (index, course, period) in &classes {
+                    @let conflicting = conflicts.iter().any(|c| c.involves(&course.course_code, period));
+                    div style=(format!(
+                        "grid-column: {}; grid-row: {} / {}; background: hsl({}, 70%, 85%); color: #222; \
+                         border-radius: 4px; padding: 2px 4px; overflow: hidden; font-size: 0.8rem{}",
+                        period.day.index() + 2,
+                        row(period.start_time.minutes() / GRID_STEP * GRID_STEP),
+                        row(period.end_time.minutes().div_ceil(GRID_STEP) * GRID_STEP),
+                        index * 137 % 360,
+                        if conflicting { "; outline: 3px solid #c00" } else { "" }
+                    )) {
+                        strong { (course.course_code) " (" (course.section) ")" }
+                        br;
+                        (period.start_time) "–" (period.end_time)
+                        br;
+                        (period.room)
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt::Display;
+
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    post,
+    web::{self, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use chrono::NaiveDate;
+use ewubd_timetable_calendar_lib::{
+    auth,
+    calendar::{self, TimetableOptions},
+    courses::{self, Course},
+    semester::{self, Semester},
+    utils,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    Modify, OpenApi, ToSchema,
+};
+
+use super::index::LoginData;
+use crate::{
+    error::{ApiError, ErrorBody},
+    store::{self, CalendarEntry},
+    AppState,
+};
+
+/// Portal session passed as "Authorization: Bearer <token>"
+fn bearer_token(req: &HttpRequest) -> Result<String, ApiError> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "missing_token",
+                "Pass the token from /api/v1/login as \"Authorization: Bearer <token>\"",
+            )
+        })
+}
+
+/// Message of the `not_found` error for paths no handler serves
+pub const UNKNOWN_ROUTE: &str = "No such API route";
+
+fn not_found(message: &str) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "not_found", message)
+}
+
+/// Reports a request body or path the API can't read
+fn invalid_request(err: impl Display) -> actix_web::Error {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request", err.to_string()).into()
+}
+
+#[derive(Serialize, ToSchema)]
+struct LoginResponse {
+    /// Portal session, valid until the portal expires it
+    token: String,
+}
+
+/// Log in to the portal
+#[utoipa::path(
+    context_path = "/api/v1",
+    request_body = LoginData,
+    responses(
+        (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 401, description = "Wrong student ID or password", body = ErrorBody),
+        (status = 502, description = "The portal is unavailable", body = ErrorBody),
+    )
+)]
+#[post("/login")]
+pub async fn login(
+    body: web::Json<LoginData>,
+    state: web::Data<AppState>,
+) -> Result<web::Json<LoginResponse>, ApiError> {
+    let token = auth::login(&state.portal, &body.username, &body.password).await?;
+    Ok(web::Json(LoginResponse { token }))
+}
+
+/// List all semesters
+#[utoipa::path(
+    context_path = "/api/v1",
+    security(("token" = [])),
+    responses(
+        (status = 200, description = "All semesters", body = Vec<Semester>),
+        (status = 401, description = "Missing token or expired session", body = ErrorBody),
+        (status = 502, description = "The portal is unavailable", body = ErrorBody),
+    )
+)]
+#[get("/semesters")]
+pub async fn semesters(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<web::Json<Vec<Semester>>, ApiError> {
+    let client = utils::build_authenticated_client(&state.portal, &bearer_token(&req)?)?;
+    let semesters = semester::get_all_semesters(&client, &state.portal).await?;
+    Ok(web::Json(semesters))
+}
+
+/// List the courses taken in a semester along with their periods
+#[utoipa::path(
+    context_path = "/api/v1",
+    security(("token" = [])),
+    params(("semester_id" = u16, Path, description = "Semester ID as listed by /semesters")),
+    responses(
+        (status = 200, description = "Courses of the semester", body = Vec<Course>),
+        (status = 401, description = "Missing token or expired session", body = ErrorBody),
+        (status = 422, description = "A course has a time slot that can't be read", body = ErrorBody),
+        (status = 502, description = "The portal is unavailable", body = ErrorBody),
+    )
+)]
+#[get("/semesters/{semester_id}/courses")]
+pub async fn semester_courses(
+    req: HttpRequest,
+    path: web::Path<u16>,
+    state: web::Data<AppState>,
+) -> Result<web::Json<Vec<Course>>, ApiError> {
+    let client = utils::build_authenticated_client(&state.portal, &bearer_token(&req)?)?;
+    let courses = courses::get_courses(&client, &state.portal, path.into_inner()).await?;
+    Ok(web::Json(courses))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CalendarRequest {
+    semester_id: u16,
+    name: String,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    #[serde(default)]
+    options: TimetableOptions,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CalendarResponse {
+    id: String,
+    /// Where the calendar can be downloaded from until it expires
+    url: String,
+    expires_in: u64,
+}
+
+/// Generate the timetable calendar of a semester
+#[utoipa::path(
+    context_path = "/api/v1",
+    security(("token" = [])),
+    request_body = CalendarRequest,
+    responses(
+        (status = 201, description = "Calendar generated", body = CalendarResponse),
+        (status = 400, description = "The request body can't be read", body = ErrorBody),
+        (status = 401, description = "Missing token or expired session", body = ErrorBody),
+        (status = 422, description = "The options don't fit the timetable", body = ErrorBody),
+        (status = 502, description = "The portal is unavailable", body = ErrorBody),
+    )
+)]
+#[post("/calendars")]
+pub async fn create_calendar(
+    req: HttpRequest,
+    body: web::Json<CalendarRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let token = bearer_token(&req)?;
+    let CalendarRequest {
+        semester_id,
+        name,
+        start_date,
+        end_date,
+        mut options,
+    } = body.into_inner();
+    options.semester_id = Some(semester_id);
+
+    let client = utils::build_authenticated_client(&state.portal, &token)?;
+    let courses = courses::get_courses(&client, &state.portal, semester_id).await?;
+    let ical = calendar::build_timetable(courses, &name, start_date, end_date, &options)?;
+
+    let id = store::calendar_id(&token, semester_id, &name);
+    let calendars = state.calendars.clone();
+    let calendar_id = id.clone();
+    web::block(move || {
+        // unchanged events keep their DTSTAMP so calendar apps don't reimport them
+        let ical = match calendars.get(&calendar_id)? {
+            Some(previous) => calendar::reuse_stamps(&ical, &previous.ical),
+            None => ical,
+        };
+        calendars.insert(&calendar_id, CalendarEntry::new(ical))
+    })
+    .await?
+    .map_err(|_| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            "Cannot store calendar",
+        )
+    })?;
+
+    let url = format!("/api/v1/calendars/{}", id);
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, url.clone()))
+        .json(CalendarResponse {
+            id,
+            url,
+            expires_in: state.calendar_ttl.as_secs(),
+        }))
+}
+
+/// Download a generated calendar
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(("id" = String, Path, description = "ID returned when the calendar was generated")),
+    responses(
+        (status = 200, description = "The calendar", body = String, content_type = "text/calendar"),
+        (status = 404, description = "The calendar doesn't exist or expired", body = ErrorBody),
+    )
+)]
+#[get("/calendars/{id}")]
+pub async fn download_calendar(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let calendars = state.calendars.clone();
+    let id = path.into_inner();
+    let entry = web::block(move || calendars.get(&id))
+        .await?
+        .map_err(|_| {
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+                "Cannot access calendars",
+            )
+        })?
+        .filter(|entry| !entry.is_expired(state.calendar_ttl))
+        .ok_or_else(|| not_found("Calendar doesn't exist"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar")
+        .body(entry.ical))
+}
+
+struct TokenAuth;
+
+impl Modify for TokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
+/// OpenAPI document of the JSON API, built from the handlers above
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "EWU Timetable API"),
+    paths(login, semesters, semester_courses, create_calendar, download_calendar),
+    modifiers(&TokenAuth)
+)]
+pub struct ApiDoc;
+
+#[get("/api/openapi.json")]
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Registers the JSON API under /api/v1 and its OpenAPI document
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(openapi_json).service(
+        web::scope("/api/v1")
+            .app_data(web::JsonConfig::default().error_handler(|e, _| invalid_request(e)))
+            .app_data(web::PathConfig::default().error_handler(|e, _| invalid_request(e)))
+            .service(login)
+            .service(semesters)
+            .service(semester_courses)
+            .service(create_calendar)
+            .service(download_calendar)
+            .default_service(web::to(|| async {
+                Err::<HttpResponse, _>(not_found(UNKNOWN_ROUTE))
+            })),
+    );
+}
@@ -2,35 +2,44 @@ use std::collections::HashMap;
 
 use actix_web::{error, get, http, post, web, HttpRequest, HttpResponse};
 use chrono::NaiveDate;
-use ewubd_timetable_calendar_lib::{calendar, courses, periods, semester, utils};
+use ewubd_timetable_calendar_lib::{
+    calendar::{self, Reminders, TimetableOptions},
+    conflicts, courses, diff, exams, holidays, import, overrides, pdf, semester, utils,
+};
 use maud::{html, Markup};
-use reqwest::StatusCode;
 use serde::Deserialize;
 
-use crate::{partials::page, AppState, CalendarEntry};
+use crate::{
+    error::to_http_error,
+    partials::{page, week_grid},
+    session::Session,
+    store::{self, CalendarEntry},
+    subscription::{RefreshCredential, SealedCredential, Subscription},
+    AppState,
+};
 
 #[get("/dashboard")]
-pub async fn dashboard(req: HttpRequest) -> Result<Markup, error::Error> {
-    let session_cookie = utils::get_session_cookie(&req)?;
-
-    // TODO: check if session is valid
+pub async fn dashboard(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<Markup, error::Error> {
+    let session = Session::from_request(&req, &state.session_key)?;
 
-    let client = utils::build_authenticated_client(&session_cookie)?;
+    let client =
+        utils::build_authenticated_client(&state.portal, &session.portal).map_err(to_http_error)?;
 
-    let semesters = semester::get_all_semesters(&client).await.map_err(|e| {
-        match e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
-            Some(StatusCode::UNAUTHORIZED) => error::ErrorUnauthorized("Unauthorized"),
-            _ => error::ErrorInternalServerError("Internal server error"),
-        }
-    })?;
+    let semesters = semester::get_all_semesters(&client, &state.portal)
+        .await
+        .map_err(to_http_error)?;
 
     let markup = page(
         "Welcome!",
-        true,
+        Some(&session.csrf_token),
         html! {
             div {
                 h2 { "Fetch timetable" }
                 form action="/dashboard/timetable" method="post" {
+                    input type="hidden" name="csrf_token" value=(session.csrf_token);
                     label for="semester" { "Select Semester" };
                     select id="semester" name="semester" {
                         @for semester in semesters {
@@ -50,34 +59,49 @@ pub async fn dashboard(req: HttpRequest) -> Result<Markup, error::Error> {
 #[derive(Deserialize)]
 struct TimetableForm {
     semester: String,
+    #[serde(default)]
+    csrf_token: String,
 }
 
 #[post("/dashboard/timetable")]
 pub async fn timetable(
     req: HttpRequest,
     form: web::Form<TimetableForm>,
+    state: web::Data<AppState>,
 ) -> Result<Markup, error::Error> {
-    let session_cookie = utils::get_session_cookie(&req)?;
+    let session = Session::from_request(&req, &state.session_key)?;
+    session.check_csrf(&form.csrf_token)?;
 
-    let client = utils::build_authenticated_client(&session_cookie)?;
+    let client =
+        utils::build_authenticated_client(&state.portal, &session.portal).map_err(to_http_error)?;
 
-    let mut semester_param = form.semester.split(" ");
-    let semester_id = semester_param.next().unwrap().parse::<u16>().unwrap();
-    let semester_name = semester_param.next().unwrap();
+    // the semester option's value is "<id> <name>"
+    let (semester_id, semester_name) = form
+        .semester
+        .split_once(' ')
+        .and_then(|(id, name)| Some((id.parse::<u16>().ok()?, name)))
+        .ok_or_else(|| error::ErrorBadRequest("Invalid semester"))?;
 
-    let courses = courses::get_courses(&client, semester_id)
+    let courses = courses::get_courses(&client, &state.portal, semester_id)
         .await
-        .map_err(
-            |e| match e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
-                Some(StatusCode::UNAUTHORIZED) => error::ErrorUnauthorized("Unauthorized"),
-                _ => error::ErrorInternalServerError("Internal server error"),
-            },
-        )?;
+        .map_err(to_http_error)?;
+    let conflicts = conflicts::find_conflicts(&courses);
 
     let body = page(
         &format!("Timetable for Semester {}", form.semester),
-        true,
+        Some(&session.csrf_token),
         html! {
+            @if !conflicts.is_empty() {
+                article {
+                    h2 { "Conflicts" }
+                    ul {
+                        @for conflict in &conflicts {
+                            li { mark { (conflict) } }
+                        }
+                    }
+                }
+            }
+            (week_grid(&courses, &conflicts))
             table {
                 tr {
                     th { "Course Code" }
@@ -93,7 +117,12 @@ pub async fn timetable(
                         td {
                             ul {
                                 @for period in &course.periods {
-                                    li { (periods::Period::weekday_name(period.day)) " " (period.start_time)"–"(period.end_time) " @ " (period.room) }
+                                    @let slot = html! { (period.day) " " (period.start_time)"–"(period.end_time) " @ " (period.room) };
+                                    @if conflicts.iter().any(|c| c.involves(&course.course_code, period)) {
+                                        li { mark { (slot) } }
+                                    } @else {
+                                        li { (slot) }
+                                    }
                                 }
                             }
                         }
@@ -103,6 +132,7 @@ pub async fn timetable(
             article {
                 h2 { "Generate timetable calendar" }
                 form action="/dashboard/timetable/generate" method="post" {
+                    input type="hidden" name="csrf_token" value=(session.csrf_token);
                     input type="hidden" name="semester_id" value=(semester_id);
                     label for="semester_name" { "Semester Name" };
                     input type="text" name="semester_name" value=(semester_name.replace("-", " "));
@@ -113,6 +143,29 @@ pub async fn timetable(
                     label for="end_date" { "Semester End Date" };
                     input type="date" name="end_date" placeholder="End Date" required;
                     br;
+                    label for="holidays" { "Holidays and closures" };
+                    small { "One per line as \"YYYY-MM-DD, Name\", \"YYYY-MM-DD..YYYY-MM-DD, Name\" or \"*-MM-DD, Name\" for every year. Classes are skipped on these days." }
+                    textarea id="holidays" name="holidays" rows="8" { (holidays::BUNDLED_HOLIDAYS) }
+                    br;
+                    label for="overrides" { "Schedule changes" };
+                    small { "One per line as \"<course>, YYYY-MM-DD, cancel\", \"<course>, YYYY-MM-DD, add 8:30AM-10:00AM @ Room\" for a make-up class, or \"<course>, YYYY-MM-DD, move YYYY-MM-DD 1:00PM-2:30PM @ Room\" where the new date, time and room are each optional." }
+                    textarea id="overrides" name="overrides" rows="4" {}
+                    br;
+                    label for="exams" { "Exams" };
+                    small { "One per line as \"<course>,midterm,YYYY-MM-DD,9:00AM,11:00AM,Room\" or with \"final\", the room is optional. A JSON list is accepted too. Weekly classes end before the first final exam." }
+                    textarea id="exams" name="exams" rows="4" {}
+                    br;
+                    label for="class_reminder" { "Remind me before classes (minutes)" };
+                    input type="number" id="class_reminder" name="class_reminder" min="0" placeholder="No reminder";
+                    label for="lab_reminder" { "Remind me before labs (minutes)" };
+                    input type="number" id="lab_reminder" name="lab_reminder" min="0" placeholder="Same as classes";
+                    br;
+                    details {
+                        summary { "Keep the subscription in sync after the portal session expires (optional)" }
+                        p { small { "Your portal login will be stored on the server until you revoke the subscription." } }
+                        input type="text" name="sync_username" placeholder="Student ID";
+                        input type="password" name="sync_password" placeholder="Password";
+                    }
                     input type="submit" value="Generate Calendar";
                 }
             }
@@ -128,6 +181,33 @@ struct GenerateForm {
     semester_name: String,
     start_date: NaiveDate,
     end_date: NaiveDate,
+    #[serde(default)]
+    holidays: String,
+    #[serde(default)]
+    overrides: String,
+    #[serde(default)]
+    exams: String,
+    #[serde(default)]
+    class_reminder: String,
+    #[serde(default)]
+    lab_reminder: String,
+    #[serde(default)]
+    sync_username: String,
+    #[serde(default)]
+    sync_password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+/// Parses an optional number of minutes from a form field left empty for none
+fn parse_minutes(field: &str) -> Result<Option<u32>, error::Error> {
+    match field.trim() {
+        "" => Ok(None),
+        minutes => minutes
+            .parse()
+            .map(Some)
+            .map_err(|_| error::ErrorBadRequest("Reminders must be a number of minutes")),
+    }
 }
 
 #[post("/dashboard/timetable/generate")]
@@ -136,52 +216,118 @@ pub async fn generate(
     form: web::Form<GenerateForm>,
     state: web::Data<AppState>,
 ) -> Result<Markup, error::Error> {
-    let session_cookie = utils::get_session_cookie(&req)?;
+    let session = Session::from_request(&req, &state.session_key)?;
+    session.check_csrf(&form.csrf_token)?;
+    let session_cookie = session.portal;
 
-    let client = utils::build_authenticated_client(&session_cookie)?;
+    let client =
+        utils::build_authenticated_client(&state.portal, &session_cookie).map_err(to_http_error)?;
 
     let GenerateForm {
         semester_id,
         semester_name,
         start_date,
         end_date,
+        holidays,
+        overrides,
+        exams,
+        class_reminder,
+        lab_reminder,
+        sync_username,
+        sync_password,
+        ..
     } = form.into_inner();
 
-    let courses = courses::get_courses(&client, semester_id)
+    let options = TimetableOptions {
+        semester_id: Some(semester_id),
+        holidays: holidays::parse_holidays(&holidays).map_err(to_http_error)?,
+        overrides: overrides::parse_overrides(&overrides).map_err(to_http_error)?,
+        exams: exams::parse_exams(&exams).map_err(to_http_error)?,
+        reminders: Reminders {
+            class: parse_minutes(&class_reminder)?,
+            lab: parse_minutes(&lab_reminder)?,
+        },
+    };
+
+    let courses = courses::get_courses(&client, &state.portal, semester_id)
         .await
-        .map_err(
-            |e| match e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
-                Some(StatusCode::UNAUTHORIZED) => error::ErrorUnauthorized("Unauthorized"),
-                _ => error::ErrorInternalServerError("Internal server error"),
-            },
-        )?;
-
-    let ical = calendar::build_timetable(courses, &semester_name, start_date, end_date)?;
-
-    let id = xxhash_rust::xxh3::xxh3_64(
-        format!("{session_cookie}{semester_id}{semester_name}").as_bytes(),
-    )
-    .to_string();
-
-    let mut calendars = state
-        .calendars
-        .lock()
+        .map_err(to_http_error)?;
+
+    let id = store::calendar_id(&session_cookie, semester_id, &semester_name);
+    let owner = store::owner_key(&state.session_key, &session.student_id, semester_id);
+
+    // the student's subscription for this semester keeps the courses it was
+    // last generated from, so section, room and lecturer changes show up
+    // however long ago that was
+    let calendars = state.calendars.clone();
+    let owner_key = owner.clone();
+    let existing = web::block(move || calendars.find_subscription(&owner_key))
+        .await?
         .map_err(|_| error::ErrorInternalServerError("Cannot access calendars"))?;
+    let changes = existing
+        .as_ref()
+        .map(|previous| diff::diff(&previous.generated_courses, &courses));
+    let generated_courses = courses.clone();
 
-    calendars.insert(
-        id.clone(),
-        CalendarEntry {
-            created_at: std::time::Instant::now(),
-            ical,
-        },
+    let ical = calendar::build_timetable(courses, &semester_name, start_date, end_date, &options)
+        .map_err(to_http_error)?;
+    // unchanged events keep their DTSTAMP so calendar apps don't reimport them
+    let ical = match &existing {
+        Some(previous) => calendar::reuse_stamps(&ical, &previous.ical),
+        None => ical,
+    };
+
+    let credential = if sync_username.is_empty() || sync_password.is_empty() {
+        RefreshCredential::Session {
+            cookie: session_cookie,
+        }
+    } else {
+        RefreshCredential::Login {
+            username: sync_username,
+            password: sync_password,
+            session: Some(session_cookie),
+        }
+    };
+    let overrides = overrides::format_overrides(&options.overrides);
+    let subscription = Subscription::new(
+        semester_id,
+        semester_name.clone(),
+        start_date,
+        end_date,
+        options,
+        SealedCredential::seal(&credential, &state.session_key),
+        ical.clone(),
     );
+    // regenerating keeps the existing subscription URL working
+    let mut subscription = match existing {
+        Some(existing) => Subscription {
+            token: existing.token,
+            ..subscription
+        },
+        None => subscription,
+    };
+    subscription.owner = Some(owner);
+    subscription.generated_courses = generated_courses;
+    let token = subscription.token.clone();
+
+    let calendars = state.calendars.clone();
+    let calendar_id = id.clone();
+    web::block(move || {
+        calendars.insert(&calendar_id, CalendarEntry::new(ical))?;
+        calendars.put_subscription(&subscription)
+    })
+    .await?
+    .map_err(|_| error::ErrorInternalServerError("Cannot store calendar"))?;
 
-    let host = req.connection_info().host().to_string();
-    let calendar_path = format!("/dashboard/timetable/download?id={}", id);
+    let host = match &state.public_host {
+        Some(host) => host.clone(),
+        None => req.connection_info().host().to_string(),
+    };
+    let subscription_path = format!("/subscriptions/{}.ics", token);
 
     Ok(page(
         "Calendar Generated",
-        true,
+        Some(&session.csrf_token),
         html! {
             p { "Calendar for timetable generated successfully." }
             article {
@@ -190,41 +336,112 @@ pub async fn generate(
                 "Start Date: " (start_date); br;
                 "End Date: " (end_date)
             }
-            p { "Note: Links will expire after 15 minutes" }
+            @if let Some(changes) = &changes {
+                article {
+                    h2 { "What changed since last generation" }
+                    @if changes.is_empty() {
+                        p { "Nothing changed." }
+                    } @else {
+                        ul {
+                            @for change in changes {
+                                li { (change) }
+                            }
+                        }
+                    }
+                }
+            }
+            p { "The subscription links stay in sync with the portal until revoked. The download link expires after " (state.calendar_ttl.as_secs() / 60) " minutes." }
             p {
-                a href=(format!("https://calendar.google.com/calendar/u/0/r?cid=webcal://{host}{calendar_path}")) target="_blank" { "Add to Google Calendar" }
+                a href=(format!("https://calendar.google.com/calendar/u/0/r?cid=webcal://{host}{subscription_path}")) target="_blank" { "Add to Google Calendar" }
+            }
+            p {
+                a href=(format!("webcal://{host}{subscription_path}")) { "Subscribe in calendar app" }
             }
             p {
                 a href=(format!("/dashboard/timetable/download?id={}", id)) { "Download as iCal" }
             }
+            p {
+                a href=(format!("/dashboard/timetable/download.pdf?id={}", id)) { "Download printable PDF" }
+            }
+            details {
+                summary { "Edit schedule changes" }
+                form action="/subscriptions/overrides" method="post" {
+                    input type="hidden" name="csrf_token" value=(session.csrf_token);
+                    input type="hidden" name="token" value=(token);
+                    textarea name="overrides" rows="4" { (overrides) }
+                    input type="submit" value="Update subscription";
+                }
+            }
+            form action="/subscriptions/revoke" method="post" {
+                input type="hidden" name="csrf_token" value=(session.csrf_token);
+                input type="hidden" name="token" value=(token);
+                input type="submit" value="Revoke subscription";
+            }
         },
     ))
 }
 
-#[get("/dashboard/timetable/download")]
-pub async fn download(
-    query: web::Query<HashMap<String, String>>,
-    state: web::Data<AppState>,
-) -> Result<HttpResponse, error::Error> {
+/// Looks up the generated calendar named by the `id` query parameter
+async fn find_calendar(
+    query: &HashMap<String, String>,
+    state: &web::Data<AppState>,
+) -> Result<(String, CalendarEntry), error::Error> {
     let id = query
         .get("id")
         .ok_or(error::ErrorBadRequest("Missing id"))?;
 
-    let calendars = state
-        .calendars
-        .lock()
-        .map_err(|_| error::ErrorInternalServerError("Cannot access calendars"))?;
-
-    let entry = calendars
-        .get(id)
+    let calendars = state.calendars.clone();
+    let calendar_id = id.clone();
+    let entry = web::block(move || calendars.get(&calendar_id))
+        .await?
+        .map_err(|_| error::ErrorInternalServerError("Cannot access calendars"))?
+        .filter(|entry| !entry.is_expired(state.calendar_ttl))
         .ok_or(error::ErrorNotFound("Calendar doesn't exist"))?;
 
+    Ok((id.clone(), entry))
+}
+
+#[get("/dashboard/timetable/download")]
+pub async fn download(
+    query: web::Query<HashMap<String, String>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, error::Error> {
+    let (id, entry) = find_calendar(&query, &state).await?;
+
     Ok(HttpResponse::Ok()
         .content_type("text/calendar")
-        .insert_header((http::header::CACHE_CONTROL, "public, max-age=900"))
+        .insert_header((
+            http::header::CACHE_CONTROL,
+            format!("public, max-age={}", state.calendar_ttl.as_secs()),
+        ))
         .insert_header((
             http::header::CONTENT_DISPOSITION,
             format!("attachment; filename=timetable_{}.ics", id),
         ))
-        .body(entry.ical.to_string()))
+        .body(entry.ical))
+}
+
+#[get("/dashboard/timetable/download.pdf")]
+pub async fn download_pdf(
+    query: web::Query<HashMap<String, String>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, error::Error> {
+    let (id, entry) = find_calendar(&query, &state).await?;
+
+    let courses = import::parse_timetable(&entry.ical).map_err(to_http_error)?;
+    let name = import::parse_calendar_name(&entry.ical)
+        .map_err(to_http_error)?
+        .unwrap_or_else(|| "Timetable".to_string());
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((
+            http::header::CACHE_CONTROL,
+            format!("public, max-age={}", state.calendar_ttl.as_secs()),
+        ))
+        .insert_header((
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=timetable_{}.pdf", id),
+        ))
+        .body(pdf::build_timetable_pdf(&courses, &name)))
 }
@@ -1,73 +1,69 @@
-use actix_web::{get, http, post, web, HttpRequest, HttpResponse, Responder};
-use maud::html;
+use actix_web::{error, get, http, post, web, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::partials::page;
-use ewubd_timetable_calendar_lib::{auth, utils};
+use crate::{
+    error::to_http_error,
+    partials::{login_form, page},
+    session::{self, Session},
+    AppState,
+};
+use ewubd_timetable_calendar_lib::auth;
 
 #[get("/")]
-pub async fn index(req: HttpRequest) -> impl Responder {
-    if utils::get_session_cookie(&req).is_ok() {
+pub async fn index(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    if Session::from_request(&req, &state.session_key).is_ok() {
         return HttpResponse::build(http::StatusCode::FOUND)
             .append_header((http::header::LOCATION, "/dashboard"))
             .finish();
     }
 
-    let login_page = page(
-        "Login",
-        false,
-        html! {
-            form action="/" method="post" {
-                input type="text" name="username" placeholder="Student ID" required;
-                br;
-                input type="password" name="password" placeholder="Password" required;
-                br;
-                input type="submit" value="Login";
-            }
-        },
-    );
+    let (csrf_token, cookie) = session::login_csrf(state.secure_cookies);
+    let login_page = page("Login", None, login_form(&csrf_token));
 
     HttpResponse::Ok()
         .content_type("text/html")
+        .cookie(cookie)
         .body(login_page.into_string())
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct LoginData {
     pub username: String,
     pub password: String,
 }
 
-#[post("/")]
-pub async fn login(form: web::Form<LoginData>) -> impl Responder {
-    let login_page_res = auth::fetch_login_page().await?;
-    let session_id = auth::get_session_id(&login_page_res)?;
-    let login_page_html = login_page_res
-        .text()
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-    let (first_num, second_num) = auth::get_captcha_addends(&login_page_html)?;
+#[derive(Deserialize)]
+pub struct LoginForm {
+    #[serde(flatten)]
+    login: LoginData,
+    #[serde(default)]
+    csrf_token: String,
+}
 
-    let client = utils::build_authenticated_client(&session_id)?;
+#[post("/")]
+pub async fn login(
+    req: HttpRequest,
+    form: web::Form<LoginForm>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, error::Error> {
+    session::check_login_csrf(&req, &form.csrf_token)?;
+    let form = &form.login;
 
-    auth::authenticate(
-        &client,
-        &form.username,
-        &form.password,
-        first_num,
-        second_num,
-    )
-    .await
-    .map_err(|err| actix_web::error::ErrorUnauthorized(err.to_string()))?;
+    let session_id = auth::login(&state.portal, &form.username, &form.password)
+        .await
+        .map_err(to_http_error)?;
 
-    let mut cookie = String::new();
-    cookie.push_str(&session_id);
-    cookie.push_str("; max-age=900");
+    // the portal session is only handed to the browser inside our own
+    // encrypted cookie
+    let cookie = Session::new(session_id, form.username.clone(), state.session_ttl).to_cookie(
+        &state.session_key,
+        state.session_ttl,
+        state.secure_cookies,
+    );
 
-    let response = HttpResponse::build(http::StatusCode::FOUND)
+    Ok(HttpResponse::build(http::StatusCode::FOUND)
         .append_header((http::header::LOCATION, "/dashboard"))
-        .append_header((http::header::SET_COOKIE, session_id))
-        .finish();
-
-    Ok::<HttpResponse, actix_web::error::Error>(response)
+        .cookie(cookie)
+        .finish())
 }
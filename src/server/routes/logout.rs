@@ -1,17 +1,28 @@
-use actix_web::{error, get, HttpRequest, HttpResponse};
-use ewubd_timetable_calendar_lib::utils;
+use actix_web::{error, post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
 
-#[get("/logout")]
-pub async fn logout(req: HttpRequest) -> Result<HttpResponse, error::Error> {
-    if utils::get_session_cookie(&req).is_ok() {
-        let mut response = HttpResponse::build(actix_web::http::StatusCode::FOUND);
-        response.append_header((actix_web::http::header::LOCATION, "/"));
-        response.append_header((
-            actix_web::http::header::SET_COOKIE,
-            "ASP.NET_SessionId=; Max-Age=-1; HttpOnly",
-        ));
-        Ok(response.finish())
-    } else {
-        Ok(HttpResponse::build(actix_web::http::StatusCode::UNAUTHORIZED).finish())
-    }
+use crate::{
+    session::{self, Session},
+    AppState,
+};
+
+#[derive(Deserialize)]
+struct LogoutForm {
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[post("/logout")]
+pub async fn logout(
+    req: HttpRequest,
+    form: web::Form<LogoutForm>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, error::Error> {
+    let session = Session::from_request(&req, &state.session_key)?;
+    session.check_csrf(&form.csrf_token)?;
+
+    let mut response = HttpResponse::build(actix_web::http::StatusCode::FOUND);
+    response.append_header((actix_web::http::header::LOCATION, "/"));
+    response.cookie(session::removal_cookie(state.secure_cookies));
+    Ok(response.finish())
 }
@@ -1,3 +1,23 @@
-pub mod index;
+pub mod api;
 pub mod dashboard;
+pub mod index;
 pub mod logout;
+pub mod subscription;
+
+use actix_web::web::ServiceConfig;
+
+/// Registers all routes of the app
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.configure(api::configure);
+    cfg.service(index::index)
+        .service(index::login)
+        .service(dashboard::dashboard)
+        .service(dashboard::timetable)
+        .service(dashboard::generate)
+        .service(dashboard::download)
+        .service(dashboard::download_pdf)
+        .service(subscription::subscription)
+        .service(subscription::update_overrides)
+        .service(subscription::revoke)
+        .service(logout::logout);
+}
@@ -0,0 +1,156 @@
+use actix_web::{error, get, http, post, web, HttpRequest, HttpResponse};
+use ewubd_timetable_calendar_lib::overrides;
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    error::to_http_error, partials::page, session::Session, store, subscription::Subscription,
+    AppState,
+};
+
+#[get("/subscriptions/{token}.ics")]
+pub async fn subscription(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, error::Error> {
+    let token = path.into_inner();
+
+    let calendars = state.calendars.clone();
+    let mut subscription = web::block(move || calendars.get_subscription(&token))
+        .await?
+        .map_err(|_| error::ErrorInternalServerError("Cannot access subscriptions"))?
+        .ok_or(error::ErrorNotFound("Subscription doesn't exist"))?;
+
+    if subscription.needs_refresh() {
+        // subscribers keep getting the last good snapshot if the portal can't be used
+        if let Err(e) = subscription
+            .refresh(&state.portal, &state.session_key)
+            .await
+        {
+            log::warn!("Cannot refresh subscription: {}", e);
+        }
+
+        let calendars = state.calendars.clone();
+        let updated = subscription.clone();
+        web::block(move || calendars.put_subscription(&updated))
+            .await?
+            .map_err(|_| error::ErrorInternalServerError("Cannot store subscription"))?;
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar")
+        .insert_header((http::header::CACHE_CONTROL, "private, max-age=3600"))
+        .body(subscription.ical))
+}
+
+/// Loads the subscription behind `token` if it belongs to the logged-in
+/// student. Tokens end up in calendar apps and their URLs, so holding one
+/// doesn't prove ownership; someone else's subscription is reported as missing.
+async fn owned_subscription(
+    state: &web::Data<AppState>,
+    session: &Session,
+    token: String,
+) -> Result<Subscription, error::Error> {
+    let calendars = state.calendars.clone();
+    let found = web::block(move || calendars.get_subscription(&token))
+        .await?
+        .map_err(|_| error::ErrorInternalServerError("Cannot access subscriptions"))?;
+
+    found
+        .filter(|found| {
+            found.owner.as_deref()
+                == Some(&store::owner_key(
+                    &state.session_key,
+                    &session.student_id,
+                    found.semester_id,
+                ))
+        })
+        .ok_or(error::ErrorNotFound("Subscription doesn't exist"))
+}
+
+#[derive(Deserialize)]
+struct RevokeForm {
+    token: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[post("/subscriptions/revoke")]
+pub async fn revoke(
+    req: HttpRequest,
+    form: web::Form<RevokeForm>,
+    state: web::Data<AppState>,
+) -> Result<Markup, error::Error> {
+    let session = Session::from_request(&req, &state.session_key)?;
+    session.check_csrf(&form.csrf_token)?;
+
+    let owned = owned_subscription(&state, &session, form.into_inner().token).await?;
+
+    let calendars = state.calendars.clone();
+    let removed = web::block(move || calendars.remove_subscription(&owned.token))
+        .await?
+        .map_err(|_| error::ErrorInternalServerError("Cannot access subscriptions"))?;
+
+    if !removed {
+        return Err(error::ErrorNotFound("Subscription doesn't exist"));
+    }
+
+    Ok(page(
+        "Subscription Revoked",
+        Some(&session.csrf_token),
+        html! {
+            p { "The subscription was deleted along with any stored login. Calendar apps will stop receiving updates." }
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+struct OverridesForm {
+    token: String,
+    #[serde(default)]
+    overrides: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[post("/subscriptions/overrides")]
+pub async fn update_overrides(
+    req: HttpRequest,
+    form: web::Form<OverridesForm>,
+    state: web::Data<AppState>,
+) -> Result<Markup, error::Error> {
+    let session = Session::from_request(&req, &state.session_key)?;
+    session.check_csrf(&form.csrf_token)?;
+
+    let OverridesForm {
+        token, overrides, ..
+    } = form.into_inner();
+    let overrides = overrides::parse_overrides(&overrides).map_err(to_http_error)?;
+
+    let mut updated = owned_subscription(&state, &session, token).await?;
+
+    // rebuilt right away so that invalid changes are reported instead of stored,
+    // which works even when the stored session has expired
+    updated.options.overrides = overrides;
+    updated.rebuild().map_err(to_http_error)?;
+
+    let calendars = state.calendars.clone();
+    let stored = updated.clone();
+    web::block(move || calendars.put_subscription(&stored))
+        .await?
+        .map_err(|_| error::ErrorInternalServerError("Cannot store subscription"))?;
+
+    Ok(page(
+        "Subscription Updated",
+        Some(&session.csrf_token),
+        html! {
+            p { "The schedule changes were saved. Calendar apps will pick them up on their next sync." }
+            form action="/subscriptions/overrides" method="post" {
+                input type="hidden" name="csrf_token" value=(session.csrf_token);
+                input type="hidden" name="token" value=(updated.token);
+                textarea name="overrides" rows="4" { (overrides::format_overrides(&updated.options.overrides)) }
+                input type="submit" value="Update subscription";
+            }
+        },
+    ))
+}
@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use actix_web::{
+    cookie::{time, Cookie, CookieJar, Key, SameSite},
+    error, HttpRequest,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// Name of the cookie holding the encrypted [`Session`]
+pub const COOKIE_NAME: &str = "ewu_session";
+
+/// Name of the cookie the login form's CSRF token is double-submitted
+/// against, as there is no session to hold it yet
+pub const LOGIN_COOKIE_NAME: &str = "ewu_login_csrf";
+
+/// What the session cookie holds. The cookie is encrypted and authenticated
+/// with the server's key, so the portal session never reaches the browser in
+/// the clear and the contents can't be forged.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// Portal session cookie such as "ASP.NET_SessionId=..."
+    pub portal: String,
+    /// ID the student logged in with
+    pub student_id: String,
+    /// Token that every form posted during this session has to carry
+    pub csrf_token: String,
+    /// Unix time after which the session is rejected even if the browser
+    /// still sends the cookie
+    expires_at: i64,
+}
+
+fn random_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Compares in constant time so the token can't be guessed byte by byte
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+impl Session {
+    pub fn new(portal: String, student_id: String, ttl: Duration) -> Self {
+        Session {
+            portal,
+            student_id,
+            csrf_token: random_token(),
+            expires_at: Utc::now().timestamp() + ttl.as_secs() as i64,
+        }
+    }
+
+    /// Encrypts the session into a cookie that can't be read by scripts and
+    /// isn't sent along with cross-site POSTs. A `secure` cookie is only sent
+    /// over HTTPS.
+    pub fn to_cookie(&self, key: &Key, ttl: Duration, secure: bool) -> Cookie<'static> {
+        let cookie = Cookie::build(COOKIE_NAME, serde_json::to_string(self).unwrap())
+            .path("/")
+            .http_only(true)
+            .secure(secure)
+            .same_site(SameSite::Lax)
+            .max_age(time::Duration::seconds(ttl.as_secs() as i64))
+            .finish();
+
+        let mut jar = CookieJar::new();
+        jar.private_mut(key).add(cookie);
+        jar.get(COOKIE_NAME).unwrap().clone()
+    }
+
+    /// Reads the session from the request's cookie, rejecting it as
+    /// unauthorized if it is missing, tampered with or expired
+    pub fn from_request(req: &HttpRequest, key: &Key) -> Result<Self, error::Error> {
+        let unauthorized = || error::ErrorUnauthorized("Session cookie not found");
+
+        let mut jar = CookieJar::new();
+        jar.add_original(req.cookie(COOKIE_NAME).ok_or_else(unauthorized)?);
+        let cookie = jar.private(key).get(COOKIE_NAME).ok_or_else(unauthorized)?;
+
+        serde_json::from_str::<Session>(cookie.value())
+            .ok()
+            .filter(|session| session.expires_at > Utc::now().timestamp())
+            .ok_or_else(unauthorized)
+    }
+
+    /// Checks the CSRF token posted with a form against the session's
+    pub fn check_csrf(&self, token: &str) -> Result<(), error::Error> {
+        if tokens_match(&self.csrf_token, token) {
+            Ok(())
+        } else {
+            Err(error::ErrorForbidden("Invalid CSRF token"))
+        }
+    }
+}
+
+/// A CSRF token for the login form along with the cookie that has to come
+/// back with it, which keeps other sites from logging a browser into their
+/// own account
+pub fn login_csrf(secure: bool) -> (String, Cookie<'static>) {
+    let token = random_token();
+    let cookie = Cookie::build(LOGIN_COOKIE_NAME, token.clone())
+        .path("/")
+        .http_only(true)
+        .secure(secure)
+        .same_site(SameSite::Lax)
+        .finish();
+    (token, cookie)
+}
+
+/// Checks the CSRF token posted with the login form against its cookie
+pub fn check_login_csrf(req: &HttpRequest, token: &str) -> Result<(), error::Error> {
+    match req.cookie(LOGIN_COOKIE_NAME) {
+        Some(cookie) if tokens_match(cookie.value(), token) => Ok(()),
+        _ => Err(error::ErrorForbidden("Invalid CSRF token")),
+    }
+}
+
+/// Cookie that makes the browser delete the session cookie
+pub fn removal_cookie(secure: bool) -> Cookie<'static> {
+    let mut cookie = Cookie::build(COOKIE_NAME, "")
+        .path("/")
+        .http_only(true)
+        .secure(secure)
+        .same_site(SameSite::Lax)
+        .finish();
+    cookie.make_removal();
+    cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(cookie: Cookie) -> HttpRequest {
+        TestRequest::default().cookie(cookie).to_http_request()
+    }
+
+    #[test]
+    fn round_trips_through_encrypted_cookie() {
+        let key = Key::generate();
+        let session = Session::new(
+            "ASP.NET_SessionId=abc".to_string(),
+            "2021-1-60-001".to_string(),
+            Duration::from_secs(900),
+        );
+        let cookie = session.to_cookie(&key, Duration::from_secs(900), true);
+
+        assert!(!cookie.value().contains("abc"));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.max_age(), Some(time::Duration::seconds(900)));
+
+        let read = Session::from_request(&request(cookie.clone()), &key).unwrap();
+        assert_eq!(read.portal, "ASP.NET_SessionId=abc");
+        assert!(read.check_csrf(&session.csrf_token).is_ok());
+        assert!(read.check_csrf("").is_err());
+
+        // another server's key can't decrypt it
+        assert!(Session::from_request(&request(cookie), &Key::generate()).is_err());
+    }
+
+    #[test]
+    fn login_token_must_match_its_cookie() {
+        let (token, cookie) = login_csrf(true);
+        assert_eq!(cookie.http_only(), Some(true));
+
+        let req = request(cookie);
+        assert!(check_login_csrf(&req, &token).is_ok());
+        assert!(check_login_csrf(&req, &login_csrf(true).0).is_err());
+        assert!(check_login_csrf(&TestRequest::default().to_http_request(), &token).is_err());
+    }
+
+    #[test]
+    fn rejects_expired_session() {
+        let key = Key::generate();
+        let session = Session::new(
+            "ASP.NET_SessionId=abc".to_string(),
+            "2021-1-60-001".to_string(),
+            Duration::ZERO,
+        );
+        let cookie = session.to_cookie(&key, Duration::from_secs(900), true);
+
+        assert!(Session::from_request(&request(cookie), &key).is_err());
+    }
+
+    #[test]
+    fn insecure_cookie_for_plain_http() {
+        let session = Session::new(
+            "ASP.NET_SessionId=abc".to_string(),
+            "2021-1-60-001".to_string(),
+            Duration::from_secs(900),
+        );
+
+        let cookie = session.to_cookie(&Key::generate(), Duration::from_secs(900), false);
+        assert_eq!(cookie.secure(), Some(false));
+        assert_eq!(removal_cookie(false).secure(), Some(false));
+    }
+}
@@ -0,0 +1,233 @@
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use super::{CalendarEntry, CalendarStore};
+use crate::subscription::Subscription;
+
+/// Stores each calendar as `<id>.ics` in a directory, using the file's
+/// modification time as its creation time. Subscriptions are stored as
+/// `subscriptions/<token>.json`, and `subscriptions/owners/<owner>.token`
+/// holds the token of each owner's subscription.
+///
+/// Since subscriptions hold portal credentials, the directories are only
+/// accessible to the server's user and the files only readable by it.
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+/// Returns `None` for IDs that could escape the directory
+fn file_path(dir: &Path, id: &str, extension: &str) -> Option<PathBuf> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| dir.join(format!("{}.{}", id, extension)))
+}
+
+/// Creates the directory if needed and makes it private to the server's user
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    #[cfg(unix)]
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    Ok(())
+}
+
+/// Creates or replaces a file that only the server's user can read. It is
+/// written next to its destination and renamed into place, so that readers
+/// see either the old or the new contents and never a partial write.
+fn write_private(path: &Path, contents: &[u8], modified: Option<SystemTime>) -> io::Result<()> {
+    let temp = path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+
+    let mut options = fs::File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let written = options.open(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        match modified {
+            Some(modified) => file.set_modified(modified),
+            None => Ok(()),
+        }
+    });
+    let result = written.and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn invalid_id() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Invalid calendar ID")
+}
+
+impl FileStore {
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let subscriptions = dir.as_ref().join("subscriptions");
+        for dir in [dir.as_ref(), &subscriptions, &subscriptions.join("owners")] {
+            create_private_dir(dir)?;
+        }
+        Ok(FileStore {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        file_path(&self.dir, id, "ics")
+    }
+
+    fn subscription_path(&self, token: &str) -> Option<PathBuf> {
+        file_path(&self.dir.join("subscriptions"), token, "json")
+    }
+
+    fn owner_path(&self, owner: &str) -> Option<PathBuf> {
+        file_path(
+            &self.dir.join("subscriptions").join("owners"),
+            owner,
+            "token",
+        )
+    }
+}
+
+impl CalendarStore for FileStore {
+    fn get(&self, id: &str) -> io::Result<Option<CalendarEntry>> {
+        let Some(path) = self.path(id) else {
+            return Ok(None);
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(ical) => Ok(Some(CalendarEntry {
+                created_at: fs::metadata(&path)?.modified()?,
+                ical,
+            })),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn insert(&self, id: &str, entry: CalendarEntry) -> io::Result<()> {
+        let path = self
+            .path(id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid calendar ID"))?;
+
+        write_private(&path, entry.ical.as_bytes(), Some(entry.created_at))
+    }
+
+    fn remove_expired(&self, ttl: Duration) -> io::Result<usize> {
+        let mut removed = 0;
+
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_none_or(|ext| ext != "ics") {
+                continue;
+            }
+
+            let entry = CalendarEntry {
+                created_at: fs::metadata(&path)?.modified()?,
+                ical: String::new(),
+            };
+            if entry.is_expired(ttl) {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn get_subscription(&self, token: &str) -> io::Result<Option<Subscription>> {
+        let Some(path) = self.subscription_path(token) else {
+            return Ok(None);
+        };
+
+        match fs::read(&path) {
+            Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn find_subscription(&self, owner: &str) -> io::Result<Option<Subscription>> {
+        let Some(path) = self.owner_path(owner) else {
+            return Ok(None);
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(token) => Ok(self
+                .get_subscription(&token)?
+                .filter(|subscription| subscription.owner.as_deref() == Some(owner))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put_subscription(&self, subscription: &Subscription) -> io::Result<()> {
+        let path = self
+            .subscription_path(&subscription.token)
+            .ok_or_else(invalid_id)?;
+        write_private(&path, &serde_json::to_vec(subscription)?, None)?;
+
+        if let Some(owner) = &subscription.owner {
+            write_private(
+                &self.owner_path(owner).ok_or_else(invalid_id)?,
+                subscription.token.as_bytes(),
+                None,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn remove_subscription(&self, token: &str) -> io::Result<bool> {
+        let Some(path) = self.subscription_path(token) else {
+            return Ok(false);
+        };
+
+        let owner = self.get_subscription(token)?.and_then(|s| s.owner);
+        if let Some(owner_path) = owner.and_then(|owner| self.owner_path(&owner)) {
+            // the owner may have a newer subscription by now
+            if fs::read_to_string(&owner_path).is_ok_and(|indexed| indexed == token) {
+                fs::remove_file(owner_path)?;
+            }
+        }
+
+        match fs::remove_file(path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    fn remove_idle_subscriptions(&self, idle: Duration) -> io::Result<usize> {
+        let mut removed = 0;
+
+        for file in fs::read_dir(self.dir.join("subscriptions"))? {
+            let path = file?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            // one damaged file shouldn't keep the others from being cleaned up
+            let subscription = match fs::read(&path).and_then(|json| {
+                serde_json::from_slice::<Subscription>(&json).map_err(io::Error::from)
+            }) {
+                Ok(subscription) => subscription,
+                // removed since the directory was listed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    log::warn!("Skipping unreadable subscription {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if subscription.is_idle(idle) && self.remove_subscription(&subscription.token)? {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
@@ -0,0 +1,68 @@
+use std::{collections::HashMap, io, sync::Mutex, time::Duration};
+
+use super::{CalendarEntry, CalendarStore};
+use crate::subscription::Subscription;
+
+/// Keeps calendars in memory, they are lost on restart
+#[derive(Default)]
+pub struct MemoryStore {
+    calendars: Mutex<HashMap<String, CalendarEntry>>,
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> io::Result<std::sync::MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|_| io::Error::other("Cannot access calendars"))
+}
+
+impl MemoryStore {
+    fn lock(&self) -> io::Result<std::sync::MutexGuard<'_, HashMap<String, CalendarEntry>>> {
+        lock(&self.calendars)
+    }
+}
+
+impl CalendarStore for MemoryStore {
+    fn get(&self, id: &str) -> io::Result<Option<CalendarEntry>> {
+        Ok(self.lock()?.get(id).cloned())
+    }
+
+    fn insert(&self, id: &str, entry: CalendarEntry) -> io::Result<()> {
+        self.lock()?.insert(id.to_string(), entry);
+        Ok(())
+    }
+
+    fn remove_expired(&self, ttl: Duration) -> io::Result<usize> {
+        let mut calendars = self.lock()?;
+        let before = calendars.len();
+        calendars.retain(|_, entry| !entry.is_expired(ttl));
+        Ok(before - calendars.len())
+    }
+
+    fn get_subscription(&self, token: &str) -> io::Result<Option<Subscription>> {
+        Ok(lock(&self.subscriptions)?.get(token).cloned())
+    }
+
+    fn find_subscription(&self, owner: &str) -> io::Result<Option<Subscription>> {
+        Ok(lock(&self.subscriptions)?
+            .values()
+            .find(|subscription| subscription.owner.as_deref() == Some(owner))
+            .cloned())
+    }
+
+    fn put_subscription(&self, subscription: &Subscription) -> io::Result<()> {
+        lock(&self.subscriptions)?.insert(subscription.token.clone(), subscription.clone());
+        Ok(())
+    }
+
+    fn remove_subscription(&self, token: &str) -> io::Result<bool> {
+        Ok(lock(&self.subscriptions)?.remove(token).is_some())
+    }
+
+    fn remove_idle_subscriptions(&self, idle: Duration) -> io::Result<usize> {
+        let mut subscriptions = lock(&self.subscriptions)?;
+        let before = subscriptions.len();
+        subscriptions.retain(|_, subscription| !subscription.is_idle(idle));
+        Ok(before - subscriptions.len())
+    }
+}
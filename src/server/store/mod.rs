@@ -0,0 +1,327 @@
+use std::{
+    io,
+    time::{Duration, SystemTime},
+};
+
+use actix_web::cookie::Key;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::subscription::Subscription;
+
+mod file;
+mod memory;
+mod sqlite;
+
+pub use file::FileStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+#[derive(Debug, Clone, Hash, PartialEq)]
+pub struct CalendarEntry {
+    pub created_at: SystemTime,
+    pub ical: String,
+}
+
+impl CalendarEntry {
+    pub fn new(ical: String) -> Self {
+        CalendarEntry {
+            created_at: SystemTime::now(),
+            ical,
+        }
+    }
+
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        // a clock that went backwards counts as freshly created
+        self.created_at.elapsed().unwrap_or_default() >= ttl
+    }
+}
+
+/// ID of the calendar generated with a portal session for a semester, so that
+/// generating it again replaces the previous one
+pub fn calendar_id(session: &str, semester_id: u16, semester_name: &str) -> String {
+    xxhash_rust::xxh3::xxh3_64(format!("{session}{semester_id}{semester_name}").as_bytes())
+        .to_string()
+}
+
+/// Key of the subscription of a student for a semester. Student IDs are few
+/// enough to try them all, so it is an HMAC with the server's key rather than
+/// a plain hash; without the key a copy of the store doesn't tell whose
+/// subscriptions it holds.
+pub fn owner_key(key: &Key, student_id: &str, semester_id: u16) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.signing()).expect("HMAC takes any key size");
+    mac.update(format!("{student_id}/{semester_id}").as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Storage for generated calendars, keyed by calendar ID
+pub trait CalendarStore: Send + Sync {
+    fn get(&self, id: &str) -> io::Result<Option<CalendarEntry>>;
+
+    /// Inserts a calendar, replacing any existing one with the same ID
+    fn insert(&self, id: &str, entry: CalendarEntry) -> io::Result<()>;
+
+    /// Deletes calendars older than `ttl`, returns how many were deleted.
+    /// Subscriptions are kept until they are removed.
+    fn remove_expired(&self, ttl: Duration) -> io::Result<usize>;
+
+    fn get_subscription(&self, token: &str) -> io::Result<Option<Subscription>>;
+
+    /// Looks up a subscription by its [`Subscription::owner`]
+    fn find_subscription(&self, owner: &str) -> io::Result<Option<Subscription>>;
+
+    /// Inserts a subscription, replacing any existing one with the same token
+    fn put_subscription(&self, subscription: &Subscription) -> io::Result<()>;
+
+    /// Returns whether the subscription existed
+    fn remove_subscription(&self, token: &str) -> io::Result<bool>;
+
+    /// Deletes subscriptions along with their stored credentials once they
+    /// are [idle](Subscription::is_idle), returns how many were deleted
+    fn remove_idle_subscriptions(&self, idle: Duration) -> io::Result<usize>;
+}
+
+/// Builds a store from a spec of the form "memory", "file:<dir>" or "sqlite:<path>"
+pub fn from_spec(spec: &str) -> io::Result<Box<dyn CalendarStore>> {
+    match spec.split_once(':') {
+        None if spec == "memory" => Ok(Box::new(MemoryStore::default())),
+        Some(("file", dir)) => Ok(Box::new(FileStore::new(dir)?)),
+        Some(("sqlite", path)) => Ok(Box::new(SqliteStore::open(path)?)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown calendar store \"{}\"", spec),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subscription::{RefreshCredential, SealedCredential};
+    use chrono::NaiveDate;
+    use ewubd_timetable_calendar_lib::{calendar::TimetableOptions, holidays};
+
+    fn old_entry(ical: &str) -> CalendarEntry {
+        CalendarEntry {
+            created_at: SystemTime::now() - Duration::from_secs(3600),
+            ical: ical.to_string(),
+        }
+    }
+
+    /// Runs the same checks against every backend
+    fn exercise(store: &dyn CalendarStore) {
+        let key = Key::generate();
+        assert_eq!(store.get("missing").unwrap(), None);
+
+        store
+            .insert("a", CalendarEntry::new("A".to_string()))
+            .unwrap();
+        store.insert("b", old_entry("B")).unwrap();
+        assert_eq!(store.get("a").unwrap().unwrap().ical, "A");
+        assert_eq!(store.get("b").unwrap().unwrap().ical, "B");
+
+        store
+            .insert("a", CalendarEntry::new("A2".to_string()))
+            .unwrap();
+        assert_eq!(store.get("a").unwrap().unwrap().ical, "A2");
+
+        assert_eq!(store.remove_expired(Duration::from_secs(900)).unwrap(), 1);
+        assert_eq!(store.get("b").unwrap(), None);
+        assert!(store.get("a").unwrap().is_some());
+
+        let mut subscription = Subscription::new(
+            1,
+            "Fall 2024".to_string(),
+            NaiveDate::from_ymd_opt(2024, 9, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 12, 19).unwrap(),
+            TimetableOptions {
+                holidays: holidays::bundled_holidays(),
+                ..Default::default()
+            },
+            SealedCredential::seal(
+                &RefreshCredential::Session {
+                    cookie: "ASP.NET_SessionId=abc".to_string(),
+                },
+                &Key::generate(),
+            ),
+            "ICAL".to_string(),
+        );
+        assert_eq!(store.get_subscription(&subscription.token).unwrap(), None);
+        store.put_subscription(&subscription).unwrap();
+        assert_eq!(
+            store
+                .find_subscription(&owner_key(&key, "2021-1-60-001", 1))
+                .unwrap(),
+            None
+        );
+        subscription.owner = Some(owner_key(&key, "2021-1-60-001", 1));
+        subscription.ical = "ICAL2".to_string();
+        store.put_subscription(&subscription).unwrap();
+        // expiry does not apply to subscriptions
+        store.remove_expired(Duration::ZERO).unwrap();
+        assert_eq!(
+            store.get_subscription(&subscription.token).unwrap(),
+            Some(subscription.clone())
+        );
+        assert_eq!(
+            store
+                .find_subscription(&owner_key(&key, "2021-1-60-001", 1))
+                .unwrap(),
+            Some(subscription.clone())
+        );
+        assert_eq!(
+            store
+                .find_subscription(&owner_key(&key, "2021-1-60-001", 2))
+                .unwrap(),
+            None
+        );
+
+        let mut idle = subscription.clone();
+        idle.token = "idle".to_string();
+        idle.owner = Some(owner_key(&key, "2021-1-60-001", 2));
+        idle.checked_at = SystemTime::now() - Duration::from_secs(40 * 24 * 60 * 60);
+        store.put_subscription(&idle).unwrap();
+        assert_eq!(
+            store
+                .remove_idle_subscriptions(Duration::from_secs(30 * 24 * 60 * 60))
+                .unwrap(),
+            1
+        );
+        assert_eq!(store.get_subscription("idle").unwrap(), None);
+        assert_eq!(
+            store
+                .find_subscription(&owner_key(&key, "2021-1-60-001", 2))
+                .unwrap(),
+            None
+        );
+        assert!(store
+            .get_subscription(&subscription.token)
+            .unwrap()
+            .is_some());
+
+        assert!(store.remove_subscription(&subscription.token).unwrap());
+        assert_eq!(
+            store
+                .find_subscription(&owner_key(&key, "2021-1-60-001", 1))
+                .unwrap(),
+            None
+        );
+        assert!(!store.remove_subscription(&subscription.token).unwrap());
+        assert_eq!(store.get_subscription(&subscription.token).unwrap(), None);
+    }
+
+    #[test]
+    fn owner_key_depends_on_server_key() {
+        let key = Key::generate();
+        let owner = owner_key(&key, "2021-1-60-001", 1);
+
+        assert_eq!(owner, owner_key(&key, "2021-1-60-001", 1));
+        assert_eq!(owner.len(), 64);
+        assert_ne!(owner, owner_key(&key, "2021-1-60-002", 1));
+        assert_ne!(owner, owner_key(&key, "2021-1-60-001", 2));
+        assert_ne!(owner, owner_key(&Key::generate(), "2021-1-60-001", 1));
+    }
+
+    #[test]
+    fn memory_store() {
+        exercise(&MemoryStore::default());
+    }
+
+    #[test]
+    fn file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        exercise(&FileStore::new(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn sqlite_store() {
+        let dir = tempfile::tempdir().unwrap();
+        exercise(&SqliteStore::open(dir.path().join("calendars.db")).unwrap());
+    }
+
+    #[cfg(unix)]
+    fn mode(path: &std::path::Path) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stores_are_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path()).unwrap();
+        store
+            .insert("a", CalendarEntry::new("A".to_string()))
+            .unwrap();
+        assert_eq!(mode(dir.path()), 0o700);
+        assert_eq!(mode(&dir.path().join("subscriptions")), 0o700);
+        assert_eq!(mode(&dir.path().join("subscriptions/owners")), 0o700);
+        assert_eq!(mode(&dir.path().join("a.ics")), 0o600);
+
+        let path = dir.path().join("calendars.db");
+        SqliteStore::open(&path).unwrap();
+        assert_eq!(mode(&path), 0o600);
+    }
+
+    #[test]
+    fn file_store_skips_unreadable_subscriptions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path()).unwrap();
+        let mut idle = Subscription::new(
+            1,
+            "Fall 2024".to_string(),
+            NaiveDate::from_ymd_opt(2024, 9, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 12, 19).unwrap(),
+            TimetableOptions::default(),
+            SealedCredential::seal(
+                &RefreshCredential::Session {
+                    cookie: "ASP.NET_SessionId=abc".to_string(),
+                },
+                &Key::generate(),
+            ),
+            "ICAL".to_string(),
+        );
+        idle.checked_at = SystemTime::now() - Duration::from_secs(40 * 24 * 60 * 60);
+        store.put_subscription(&idle).unwrap();
+        std::fs::write(dir.path().join("subscriptions/broken.json"), "{").unwrap();
+
+        assert_eq!(
+            store
+                .remove_idle_subscriptions(Duration::from_secs(30 * 24 * 60 * 60))
+                .unwrap(),
+            1
+        );
+        // no temporary files are left behind by the writes
+        let mut left = std::fs::read_dir(dir.path().join("subscriptions"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, ["broken.json", "owners"]);
+    }
+
+    #[test]
+    fn file_store_rejects_path_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path()).unwrap();
+
+        assert!(store
+            .insert("../escape", CalendarEntry::new(String::new()))
+            .is_err());
+        assert_eq!(store.get("../escape").unwrap(), None);
+    }
+
+    #[test]
+    fn parses_spec() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(from_spec("memory").is_ok());
+        assert!(from_spec(&format!("file:{}", dir.path().display())).is_ok());
+        assert!(from_spec(&format!("sqlite:{}/db", dir.path().display())).is_ok());
+        assert!(from_spec("redis://localhost").is_err());
+    }
+}
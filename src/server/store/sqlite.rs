@@ -0,0 +1,172 @@
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::{
+    fs, io,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{CalendarEntry, CalendarStore};
+use crate::subscription::Subscription;
+
+/// Stores calendars in a SQLite database, which can be shared by several
+/// server processes on the same host. The database file is only readable by
+/// the server's user since subscriptions hold portal credentials, and SQLite
+/// gives its journal files the same permissions.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+fn to_io(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        // created empty before SQLite opens it, so it is never readable by others
+        let mut options = fs::File::options();
+        options.write(true).create(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        options.open(path.as_ref())?;
+        #[cfg(unix)]
+        fs::set_permissions(path.as_ref(), fs::Permissions::from_mode(0o600))?;
+
+        let conn = Connection::open(path).map_err(to_io)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS calendars (
+                id TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                ical TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS subscriptions (
+                token TEXT PRIMARY KEY,
+                owner TEXT,
+                checked_at INTEGER NOT NULL,
+                subscription TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS subscriptions_owner ON subscriptions (owner);",
+        )
+        .map_err(to_io)?;
+
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// The subscription whose `column` is `value`
+    fn query_subscription(&self, column: &str, value: &str) -> io::Result<Option<Subscription>> {
+        let json = self
+            .lock()?
+            .query_row(
+                &format!(
+                    "SELECT subscription FROM subscriptions WHERE {} = ?1",
+                    column
+                ),
+                params![value],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(to_io)?;
+
+        match json {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn lock(&self) -> io::Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| io::Error::other("Cannot access calendar database"))
+    }
+}
+
+impl CalendarStore for SqliteStore {
+    fn get(&self, id: &str) -> io::Result<Option<CalendarEntry>> {
+        self.lock()?
+            .query_row(
+                "SELECT created_at, ical FROM calendars WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(CalendarEntry {
+                        created_at: UNIX_EPOCH + Duration::from_secs(row.get::<_, i64>(0)? as u64),
+                        ical: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(to_io)
+    }
+
+    fn insert(&self, id: &str, entry: CalendarEntry) -> io::Result<()> {
+        self.lock()?
+            .execute(
+                "INSERT OR REPLACE INTO calendars (id, created_at, ical) VALUES (?1, ?2, ?3)",
+                params![id, unix_secs(entry.created_at), entry.ical],
+            )
+            .map_err(to_io)?;
+        Ok(())
+    }
+
+    fn remove_expired(&self, ttl: Duration) -> io::Result<usize> {
+        let cutoff = unix_secs(SystemTime::now()) - ttl.as_secs() as i64;
+        self.lock()?
+            .execute(
+                "DELETE FROM calendars WHERE created_at <= ?1",
+                params![cutoff],
+            )
+            .map_err(to_io)
+    }
+
+    fn get_subscription(&self, token: &str) -> io::Result<Option<Subscription>> {
+        self.query_subscription("token", token)
+    }
+
+    fn find_subscription(&self, owner: &str) -> io::Result<Option<Subscription>> {
+        self.query_subscription("owner", owner)
+    }
+
+    fn put_subscription(&self, subscription: &Subscription) -> io::Result<()> {
+        self.lock()?
+            .execute(
+                "INSERT OR REPLACE INTO subscriptions (token, owner, checked_at, subscription)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    subscription.token,
+                    subscription.owner,
+                    unix_secs(subscription.checked_at),
+                    serde_json::to_string(subscription)?
+                ],
+            )
+            .map_err(to_io)?;
+        Ok(())
+    }
+
+    fn remove_subscription(&self, token: &str) -> io::Result<bool> {
+        let removed = self
+            .lock()?
+            .execute("DELETE FROM subscriptions WHERE token = ?1", params![token])
+            .map_err(to_io)?;
+        Ok(removed > 0)
+    }
+
+    fn remove_idle_subscriptions(&self, idle: Duration) -> io::Result<usize> {
+        let cutoff = unix_secs(SystemTime::now()) - idle.as_secs() as i64;
+        self.lock()?
+            .execute(
+                "DELETE FROM subscriptions WHERE checked_at <= ?1",
+                params![cutoff],
+            )
+            .map_err(to_io)
+    }
+}
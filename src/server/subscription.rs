@@ -0,0 +1,206 @@
+use std::time::{Duration, SystemTime};
+
+use actix_web::cookie::{Cookie, CookieJar, Key};
+use chrono::NaiveDate;
+use ewubd_timetable_calendar_lib::{
+    auth,
+    calendar::{self, TimetableOptions},
+    courses::{self, Course},
+    error::TimetableError,
+    import,
+    portal::PortalConfig,
+    utils,
+};
+use serde::{Deserialize, Serialize};
+
+/// Subscribers are served the stored snapshot until it is this old
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// How a subscription gets back into the portal to fetch fresh courses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RefreshCredential {
+    /// The portal session of the user who subscribed, stops working once the portal expires it
+    Session { cookie: String },
+    /// Portal login the user opted into storing, used to start new sessions
+    Login {
+        username: String,
+        password: String,
+        session: Option<String>,
+    },
+}
+
+impl RefreshCredential {
+    fn session(&self) -> Option<&str> {
+        match self {
+            RefreshCredential::Session { cookie } => Some(cookie),
+            RefreshCredential::Login { session, .. } => session.as_deref(),
+        }
+    }
+}
+
+/// Name the credential is encrypted under, which binds the ciphertext to its purpose
+const SEALED_NAME: &str = "refresh_credential";
+
+/// A [`RefreshCredential`] encrypted and authenticated with the server's key,
+/// which is the only form it is stored in so that a copy of the store
+/// doesn't give away portal logins
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SealedCredential(String);
+
+impl SealedCredential {
+    pub fn seal(credential: &RefreshCredential, key: &Key) -> Self {
+        let cookie = Cookie::new(SEALED_NAME, serde_json::to_string(credential).unwrap());
+
+        let mut jar = CookieJar::new();
+        jar.private_mut(key).add(cookie);
+        SealedCredential(jar.get(SEALED_NAME).unwrap().value().to_string())
+    }
+
+    /// Decrypts the credential, `None` if it was sealed with another key
+    pub fn open(&self, key: &Key) -> Option<RefreshCredential> {
+        let mut jar = CookieJar::new();
+        jar.add_original(Cookie::new(SEALED_NAME, self.0.clone()));
+        let cookie = jar.private(key).get(SEALED_NAME)?;
+
+        serde_json::from_str(cookie.value()).ok()
+    }
+}
+
+/// A long-lived calendar URL that is rebuilt from the portal while it is polled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Secret used in the subscription URL
+    pub token: String,
+    /// Student and semester the subscription was generated for, see
+    /// [`crate::store::owner_key`]. Generating again updates it in place.
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub semester_id: u16,
+    pub semester_name: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    #[serde(default)]
+    pub options: TimetableOptions,
+    pub credential: SealedCredential,
+    /// Last calendar that was built successfully
+    pub ical: String,
+    /// Courses as of the last time the student generated the calendar, which
+    /// the next generation is compared against. Refreshes don't change them.
+    #[serde(default)]
+    pub generated_courses: Vec<Course>,
+    pub refreshed_at: SystemTime,
+    /// Last time a refresh was attempted, successful or not
+    pub checked_at: SystemTime,
+}
+
+pub fn new_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+impl Subscription {
+    pub fn new(
+        semester_id: u16,
+        semester_name: String,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        options: TimetableOptions,
+        credential: SealedCredential,
+        ical: String,
+    ) -> Self {
+        let now = SystemTime::now();
+        Subscription {
+            token: new_token(),
+            owner: None,
+            semester_id,
+            semester_name,
+            start_date,
+            end_date,
+            options,
+            credential,
+            ical,
+            generated_courses: Vec::new(),
+            refreshed_at: now,
+            checked_at: now,
+        }
+    }
+
+    /// Whether no calendar app has polled the subscription for `idle`. Polls
+    /// update `checked_at` at least every [`REFRESH_INTERVAL`].
+    pub fn is_idle(&self, idle: Duration) -> bool {
+        self.checked_at.elapsed().unwrap_or_default() >= idle
+    }
+
+    pub fn needs_refresh(&self) -> bool {
+        self.checked_at.elapsed().unwrap_or_default() >= REFRESH_INTERVAL
+    }
+
+    async fn build(&self, portal: &PortalConfig, session: &str) -> Result<String, TimetableError> {
+        let client = utils::build_authenticated_client(portal, session)?;
+        let courses = courses::get_courses(&client, portal, self.semester_id).await?;
+        calendar::build_timetable(
+            courses,
+            &self.semester_name,
+            self.start_date,
+            self.end_date,
+            &self.options,
+        )
+    }
+
+    /// Rebuilds the snapshot from the courses it already holds, for when only
+    /// the options changed and the portal doesn't have to be asked again
+    pub fn rebuild(&mut self) -> Result<(), TimetableError> {
+        let courses = import::parse_timetable(&self.ical)?;
+        let ical = calendar::build_timetable(
+            courses,
+            &self.semester_name,
+            self.start_date,
+            self.end_date,
+            &self.options,
+        )?;
+        self.ical = calendar::reuse_stamps(&ical, &self.ical);
+        Ok(())
+    }
+
+    /// Rebuilds the snapshot from the portal, logging in again if the session
+    /// expired and a login is stored. The old snapshot is kept on failure.
+    pub async fn refresh(
+        &mut self,
+        portal: &PortalConfig,
+        key: &Key,
+    ) -> Result<(), TimetableError> {
+        self.checked_at = SystemTime::now();
+
+        // a credential sealed with a key the server no longer has is as good as expired
+        let mut credential = self
+            .credential
+            .open(key)
+            .ok_or(TimetableError::SessionExpired)?;
+        let result = match credential.session() {
+            Some(session) => self.build(portal, session).await,
+            None => Err(TimetableError::SessionExpired),
+        };
+
+        let ical = match (result, &mut credential) {
+            (
+                Err(TimetableError::SessionExpired),
+                RefreshCredential::Login {
+                    username,
+                    password,
+                    session,
+                },
+            ) => {
+                let new_session = auth::login(portal, username, password).await?;
+                *session = Some(new_session.clone());
+                self.credential = SealedCredential::seal(&credential, key);
+                self.build(portal, &new_session).await?
+            }
+            (result, _) => result?,
+        };
+
+        self.ical = calendar::reuse_stamps(&ical, &self.ical);
+        self.refreshed_at = self.checked_at;
+        Ok(())
+    }
+}
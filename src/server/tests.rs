@@ -0,0 +1,997 @@
+use std::{
+    collections::BTreeSet,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{
+    body::MessageBody,
+    cookie::{time, Key, SameSite},
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    http::{header, StatusCode},
+    middleware::from_fn,
+    test,
+    web::Data,
+    App,
+};
+use ewubd_timetable_calendar_lib::{
+    calendar::TimetableOptions,
+    mock_portal::{self, MockPortal},
+    portal::PortalConfig,
+};
+
+use crate::{
+    auth_middleware, routes,
+    session::{self, Session},
+    store::{self, MemoryStore},
+    subscription::{RefreshCredential, SealedCredential, Subscription},
+    AppState,
+};
+
+fn state(portal: PortalConfig) -> Data<AppState> {
+    Data::new(AppState {
+        calendars: Arc::new(MemoryStore::default()),
+        calendar_ttl: Duration::from_secs(900),
+        portal,
+        session_ttl: Duration::from_secs(900),
+        session_key: Key::generate(),
+        secure_cookies: true,
+        public_host: None,
+    })
+}
+
+fn app(
+    state: Data<AppState>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(state)
+        .wrap(from_fn(auth_middleware))
+        .configure(routes::configure)
+}
+
+async fn body_string(res: ServiceResponse<impl MessageBody>) -> String {
+    String::from_utf8(test::read_body(res).await.to_vec()).unwrap()
+}
+
+/// The session cookie set by a login response, as sent back by the browser
+fn session_cookie(res: &ServiceResponse<impl MessageBody>) -> String {
+    let cookie = res
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == session::COOKIE_NAME)
+        .unwrap();
+    format!("{}={}", cookie.name(), cookie.value())
+}
+
+/// The CSRF token in the hidden field of a page's forms
+fn csrf_token(body: &str) -> String {
+    body.split(r#"name="csrf_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .to_string()
+}
+
+/// A post of the login form carrying a valid login CSRF token and its cookie
+fn login_request(username: &str, password: &str) -> test::TestRequest {
+    let (csrf, cookie) = session::login_csrf(true);
+    test::TestRequest::post().uri("/").cookie(cookie).set_form([
+        ("username", username),
+        ("password", password),
+        ("csrf_token", &csrf),
+    ])
+}
+
+#[actix_web::test]
+async fn login_to_download() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(res.headers().get(header::LOCATION).unwrap(), "/dashboard");
+    let cookie = res.response().cookies().next().unwrap();
+    assert_eq!(cookie.name(), session::COOKIE_NAME);
+    assert_eq!(cookie.http_only(), Some(true));
+    assert_eq!(cookie.secure(), Some(true));
+    assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+    assert_eq!(cookie.max_age(), Some(time::Duration::seconds(900)));
+    // the portal session is encrypted
+    assert!(!cookie.value().contains("mock"));
+    let session = session_cookie(&res);
+
+    let req = test::TestRequest::get()
+        .uri("/dashboard")
+        .insert_header((header::COOKIE, session.clone()))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_string(res).await;
+    assert!(body.contains(r#"<option value="1 Fall-2024">Fall-2024</option>"#));
+    let csrf = csrf_token(&body);
+
+    let req = test::TestRequest::post()
+        .uri("/dashboard/timetable")
+        .insert_header((header::COOKIE, session.clone()))
+        .set_form([("semester", "1 Fall-2024"), ("csrf_token", &csrf)])
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_string(res).await;
+    assert_eq!(csrf_token(&body), csrf);
+    assert!(body.contains("CSE101"));
+    assert!(body.contains("Jane Doe"));
+    assert!(!body.contains("ENG101"));
+    assert!(!body.contains("<mark>"));
+    // CSE101 on Monday from 8:30 to 10:00, in a grid starting at 8:00
+    assert!(body.contains("grid-column: 3; grid-row: 8 / 26;"));
+
+    let req = test::TestRequest::post()
+        .uri("/dashboard/timetable/generate")
+        .insert_header((header::COOKIE, session.clone()))
+        .set_form([
+            ("semester_id", "1"),
+            ("semester_name", "Fall 2024"),
+            ("start_date", "2024-09-01"),
+            ("end_date", "2024-12-19"),
+            ("holidays", "2024-12-16, Victory Day"),
+            ("exams", "CSE101,final,2024-12-18,9:00AM,11:00AM,AB3-302"),
+            ("class_reminder", "10"),
+            ("lab_reminder", ""),
+            ("csrf_token", &csrf),
+        ])
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_string(res).await;
+    let download_path = body
+        .split('"')
+        .find(|s| s.starts_with("/dashboard/timetable/download?id="))
+        .unwrap()
+        .to_string();
+
+    let subscription_path = body
+        .split('"')
+        .find_map(|s| s.strip_prefix("webcal://localhost:8080"))
+        .unwrap()
+        .to_string();
+
+    let req = test::TestRequest::get().uri(&download_path).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/calendar"
+    );
+    let ical = body_string(res).await;
+    assert!(ical.starts_with("BEGIN:VCALENDAR"));
+    assert!(ical.contains("SUMMARY:CSE101 (2)"));
+    assert!(ical.contains("SUMMARY:MAT101 (5)"));
+    assert!(ical.contains("SUMMARY:Victory Day"));
+    assert!(ical.contains("EXDATE;TZID=Asia/Dhaka:20241216T083000"));
+    assert!(ical.contains("SUMMARY:CSE101 Final Exam"));
+    assert!(ical.contains("TRIGGER:-PT10M"));
+
+    let req = test::TestRequest::get()
+        .uri(&download_path.replace("/download?", "/download.pdf?"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/pdf"
+    );
+    let pdf = test::read_body(res).await;
+    assert!(pdf.starts_with(b"%PDF-"));
+    assert!(String::from_utf8_lossy(&pdf).contains("(Fall 2024) Tj"));
+
+    let req = test::TestRequest::get()
+        .uri(&subscription_path)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body_string(res).await.contains("SUMMARY:CSE101 (2)"));
+}
+
+#[actix_web::test]
+async fn wrong_password_shows_login_page() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = login_request(mock_portal::USERNAME, "wrong").to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res
+        .response()
+        .cookies()
+        .all(|cookie| cookie.name() != session::COOKIE_NAME));
+    // the login form shown again works with the new token
+    let cookie = res
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == session::LOGIN_COOKIE_NAME)
+        .unwrap()
+        .into_owned();
+    let body = body_string(res).await;
+    assert!(body.contains("Please login again"));
+    assert_eq!(csrf_token(&body), cookie.value());
+}
+
+#[actix_web::test]
+async fn login_requires_csrf_token() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+    let cookie = res
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == session::LOGIN_COOKIE_NAME)
+        .unwrap()
+        .into_owned();
+    assert_eq!(csrf_token(&body_string(res).await), cookie.value());
+
+    // a form posted from another site has neither the cookie nor its token
+    let req = test::TestRequest::post()
+        .uri("/")
+        .set_form([
+            ("username", mock_portal::USERNAME),
+            ("password", mock_portal::PASSWORD),
+            ("csrf_token", cookie.value()),
+        ])
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    let req = test::TestRequest::post()
+        .uri("/")
+        .cookie(cookie.clone())
+        .set_form([
+            ("username", mock_portal::USERNAME),
+            ("password", mock_portal::PASSWORD),
+        ])
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/")
+        .cookie(cookie.clone())
+        .set_form([
+            ("username", mock_portal::USERNAME),
+            ("password", mock_portal::PASSWORD),
+            ("csrf_token", cookie.value()),
+        ])
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FOUND
+    );
+}
+
+#[actix_web::test]
+async fn logout_is_a_form_with_csrf_token() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+    let session = session_cookie(&test::call_service(&app, req).await);
+    let req = test::TestRequest::get()
+        .uri("/dashboard")
+        .insert_header((header::COOKIE, session.clone()))
+        .to_request();
+    let body = body_string(test::call_service(&app, req).await).await;
+    assert!(body.contains(r#"<form action="/logout" method="post">"#));
+    let csrf = csrf_token(&body);
+
+    // a link or image on another site can't log the student out
+    let req = test::TestRequest::get()
+        .uri("/logout")
+        .insert_header((header::COOKIE, session.clone()))
+        .to_request();
+    assert_ne!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FOUND
+    );
+    let req = test::TestRequest::post()
+        .uri("/logout")
+        .insert_header((header::COOKIE, session.clone()))
+        .set_form([("csrf_token", "0123456789abcdef0123456789abcdef")])
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/logout")
+        .insert_header((header::COOKIE, session))
+        .set_form([("csrf_token", &csrf)])
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    let removal = res.response().cookies().next().unwrap();
+    assert_eq!(removal.name(), session::COOKIE_NAME);
+    assert_eq!(removal.value(), "");
+}
+
+#[actix_web::test]
+async fn dashboard_requires_session() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = test::TestRequest::get().uri("/dashboard").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // a session that never logged in is rejected by the portal
+    let req = test::TestRequest::get()
+        .uri("/dashboard")
+        .insert_header((header::COOKIE, "ASP.NET_SessionId=unknown"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // nor is a session cookie encrypted with another key
+    let other = Session::new(
+        "ASP.NET_SessionId=unknown".to_string(),
+        mock_portal::USERNAME.to_string(),
+        Duration::from_secs(900),
+    )
+    .to_cookie(&Key::generate(), Duration::from_secs(900), true);
+    let req = test::TestRequest::get()
+        .uri("/dashboard")
+        .cookie(other)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn forms_require_csrf_token() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+    let session = session_cookie(&test::call_service(&app, req).await);
+
+    for csrf in [None, Some("0123456789abcdef0123456789abcdef")] {
+        let mut form = vec![("semester", "1 Fall-2024")];
+        form.extend(csrf.map(|csrf| ("csrf_token", csrf)));
+        let req = test::TestRequest::post()
+            .uri("/dashboard/timetable")
+            .insert_header((header::COOKIE, session.clone()))
+            .set_form(form)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let mut form = vec![
+            ("semester_id", "1"),
+            ("semester_name", "Fall 2024"),
+            ("start_date", "2024-09-01"),
+            ("end_date", "2024-12-19"),
+        ];
+        form.extend(csrf.map(|csrf| ("csrf_token", csrf)));
+        let req = test::TestRequest::post()
+            .uri("/dashboard/timetable/generate")
+            .insert_header((header::COOKIE, session.clone()))
+            .set_form(form)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        for uri in ["/subscriptions/revoke", "/subscriptions/overrides"] {
+            let mut form = vec![("token", "0123456789abcdef0123456789abcdef")];
+            form.extend(csrf.map(|csrf| ("csrf_token", csrf)));
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .insert_header((header::COOKIE, session.clone()))
+                .set_form(form)
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::FORBIDDEN, "{}", uri);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn malformed_semester_is_bad_request() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+    let session = session_cookie(&test::call_service(&app, req).await);
+    let req = test::TestRequest::get()
+        .uri("/dashboard")
+        .insert_header((header::COOKIE, session.clone()))
+        .to_request();
+    let csrf = csrf_token(&body_string(test::call_service(&app, req).await).await);
+
+    for semester in ["", "1", "one Fall-2024"] {
+        let req = test::TestRequest::post()
+            .uri("/dashboard/timetable")
+            .insert_header((header::COOKIE, session.clone()))
+            .set_form([("semester", semester), ("csrf_token", &csrf)])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{:?}", semester);
+    }
+}
+
+#[actix_web::test]
+async fn unknown_calendar_is_not_found() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = test::TestRequest::get()
+        .uri("/dashboard/timetable/download?id=missing")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn unreachable_portal_is_bad_gateway() {
+    // nothing listens on a port right after its listener is dropped
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = PortalConfig::new(&format!("http://{}", addr));
+    let app = test::init_service(app(state(config))).await;
+
+    let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+}
+
+fn stale_subscription(credential: RefreshCredential, key: &Key) -> Subscription {
+    let mut subscription = Subscription::new(
+        1,
+        "Fall 2024".to_string(),
+        chrono::NaiveDate::from_ymd_opt(2024, 9, 1).unwrap(),
+        chrono::NaiveDate::from_ymd_opt(2024, 12, 19).unwrap(),
+        TimetableOptions::default(),
+        SealedCredential::seal(&credential, key),
+        "STALE SNAPSHOT".to_string(),
+    );
+    subscription.owner = Some(store::owner_key(key, mock_portal::USERNAME, 1));
+    subscription.checked_at = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    subscription
+}
+
+#[actix_web::test]
+async fn subscription_serves_snapshot_when_session_expired() {
+    let portal = MockPortal::start().unwrap();
+    let state = state(portal.config());
+    let subscription = stale_subscription(
+        RefreshCredential::Session {
+            cookie: "ASP.NET_SessionId=expired".to_string(),
+        },
+        &state.session_key,
+    );
+    state.calendars.put_subscription(&subscription).unwrap();
+    let app = test::init_service(app(state.clone())).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/subscriptions/{}.ics", subscription.token))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_string(res).await, "STALE SNAPSHOT");
+    let stored = state
+        .calendars
+        .get_subscription(&subscription.token)
+        .unwrap()
+        .unwrap();
+    assert!(!stored.needs_refresh());
+    assert_eq!(stored.refreshed_at, subscription.refreshed_at);
+}
+
+#[actix_web::test]
+async fn subscription_logs_in_again_with_stored_login() {
+    let portal = MockPortal::start().unwrap();
+    let state = state(portal.config());
+    let subscription = stale_subscription(
+        RefreshCredential::Login {
+            username: mock_portal::USERNAME.to_string(),
+            password: mock_portal::PASSWORD.to_string(),
+            session: Some("ASP.NET_SessionId=expired".to_string()),
+        },
+        &state.session_key,
+    );
+    state.calendars.put_subscription(&subscription).unwrap();
+    let app = test::init_service(app(state.clone())).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/subscriptions/{}.ics", subscription.token))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body_string(res).await.contains("SUMMARY:CSE101 (2)"));
+    let stored = state
+        .calendars
+        .get_subscription(&subscription.token)
+        .unwrap()
+        .unwrap();
+    // the password is only stored encrypted
+    assert!(!serde_json::to_string(&stored)
+        .unwrap()
+        .contains(mock_portal::PASSWORD));
+    match stored.credential.open(&state.session_key).unwrap() {
+        RefreshCredential::Login { session, .. } => {
+            assert!(session.unwrap().starts_with("ASP.NET_SessionId=mock"))
+        }
+        credential => panic!("unexpected credential {:?}", credential),
+    }
+}
+
+#[actix_web::test]
+async fn subscription_sealed_with_another_key_serves_snapshot() {
+    let portal = MockPortal::start().unwrap();
+    let state = state(portal.config());
+    // as if the server restarted without a configured session_key
+    let subscription = stale_subscription(
+        RefreshCredential::Login {
+            username: mock_portal::USERNAME.to_string(),
+            password: mock_portal::PASSWORD.to_string(),
+            session: None,
+        },
+        &Key::generate(),
+    );
+    state.calendars.put_subscription(&subscription).unwrap();
+    let app = test::init_service(app(state)).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/subscriptions/{}.ics", subscription.token))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_string(res).await, "STALE SNAPSHOT");
+}
+
+#[actix_web::test]
+async fn revoked_subscription_is_gone() {
+    let portal = MockPortal::start().unwrap();
+    let state = state(portal.config());
+    let subscription = stale_subscription(
+        RefreshCredential::Session {
+            cookie: "ASP.NET_SessionId=expired".to_string(),
+        },
+        &state.session_key,
+    );
+    // another student's subscription whose token got around
+    let mut other = subscription.clone();
+    other.token = "0123456789abcdef0123456789abcdef".to_string();
+    other.owner = Some(store::owner_key(&state.session_key, "2021-1-60-002", 1));
+    state.calendars.put_subscription(&subscription).unwrap();
+    state.calendars.put_subscription(&other).unwrap();
+    let app = test::init_service(app(state.clone())).await;
+
+    let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+    let session = session_cookie(&test::call_service(&app, req).await);
+    let req = test::TestRequest::get()
+        .uri("/dashboard")
+        .insert_header((header::COOKIE, session.clone()))
+        .to_request();
+    let csrf = csrf_token(&body_string(test::call_service(&app, req).await).await);
+
+    let revoke = |token: &str| {
+        test::TestRequest::post()
+            .uri("/subscriptions/revoke")
+            .insert_header((header::COOKIE, session.clone()))
+            .set_form([("token", token), ("csrf_token", &csrf)])
+            .to_request()
+    };
+
+    let res = test::call_service(&app, revoke(&other.token)).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(state
+        .calendars
+        .get_subscription(&other.token)
+        .unwrap()
+        .is_some());
+
+    let res = test::call_service(&app, revoke(&subscription.token)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/subscriptions/{}.ics", subscription.token))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn subscription_overrides_are_updated() {
+    let portal = MockPortal::start().unwrap();
+    let state = state(portal.config());
+    let subscription = stale_subscription(
+        RefreshCredential::Login {
+            username: mock_portal::USERNAME.to_string(),
+            password: mock_portal::PASSWORD.to_string(),
+            session: None,
+        },
+        &state.session_key,
+    );
+    state.calendars.put_subscription(&subscription).unwrap();
+    let app = test::init_service(app(state.clone())).await;
+
+    // the snapshot is fetched once, then the portal session expires
+    let req = test::TestRequest::get()
+        .uri(&format!("/subscriptions/{}.ics", subscription.token))
+        .to_request();
+    test::call_service(&app, req).await;
+    let mut fetched = state
+        .calendars
+        .get_subscription(&subscription.token)
+        .unwrap()
+        .unwrap();
+    fetched.credential = SealedCredential::seal(
+        &RefreshCredential::Session {
+            cookie: "ASP.NET_SessionId=expired".to_string(),
+        },
+        &state.session_key,
+    );
+    state.calendars.put_subscription(&fetched).unwrap();
+
+    let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+    let session = session_cookie(&test::call_service(&app, req).await);
+    let req = test::TestRequest::get()
+        .uri("/dashboard")
+        .insert_header((header::COOKIE, session.clone()))
+        .to_request();
+    let csrf = csrf_token(&body_string(test::call_service(&app, req).await).await);
+
+    let req = test::TestRequest::post()
+        .uri("/subscriptions/overrides")
+        .insert_header((header::COOKIE, session.clone()))
+        .set_form([
+            ("token", subscription.token.as_str()),
+            ("overrides", "CSE101, 2024-10-22, cancel"),
+            ("csrf_token", &csrf),
+        ])
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    // CSE101 has no class on that Tuesday
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // another student's subscription can't be changed by whoever has its token
+    let mut other = fetched.clone();
+    other.token = "0123456789abcdef0123456789abcdef".to_string();
+    other.owner = Some(store::owner_key(&state.session_key, "2021-1-60-002", 1));
+    state.calendars.put_subscription(&other).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/subscriptions/overrides")
+        .insert_header((header::COOKIE, session.clone()))
+        .set_form([
+            ("token", other.token.as_str()),
+            ("overrides", "CSE101, 2024-10-21, cancel"),
+            ("csrf_token", &csrf),
+        ])
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let unchanged = state
+        .calendars
+        .get_subscription(&other.token)
+        .unwrap()
+        .unwrap();
+    assert!(unchanged.options.overrides.is_empty());
+
+    let req = test::TestRequest::post()
+        .uri("/subscriptions/overrides")
+        .insert_header((header::COOKIE, session))
+        .set_form([
+            ("token", subscription.token.as_str()),
+            ("overrides", "CSE101, 2024-10-21, cancel"),
+            ("csrf_token", &csrf),
+        ])
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body_string(res)
+        .await
+        .contains("CSE101, 2024-10-21, cancel"));
+
+    let stored = state
+        .calendars
+        .get_subscription(&subscription.token)
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.options.overrides.len(), 1);
+    assert!(stored
+        .ical
+        .contains("EXDATE;TZID=Asia/Dhaka:20241021T083000"));
+}
+
+#[actix_web::test]
+async fn regenerating_shows_what_changed() {
+    let portal = MockPortal::start().unwrap();
+    let state = state(portal.config());
+    let app = test::init_service(app(state.clone())).await;
+
+    let login = || async {
+        let req = login_request(mock_portal::USERNAME, mock_portal::PASSWORD).to_request();
+        let session = session_cookie(&test::call_service(&app, req).await);
+
+        let req = test::TestRequest::get()
+            .uri("/dashboard")
+            .insert_header((header::COOKIE, session.clone()))
+            .to_request();
+        let csrf = csrf_token(&body_string(test::call_service(&app, req).await).await);
+        (session, csrf)
+    };
+    let generate = |(session, csrf): &(String, String)| {
+        test::TestRequest::post()
+            .uri("/dashboard/timetable/generate")
+            .insert_header((header::COOKIE, session.clone()))
+            .set_form([
+                ("semester_id", "1"),
+                ("semester_name", "Fall 2024"),
+                ("start_date", "2024-09-01"),
+                ("end_date", "2024-12-19"),
+                ("csrf_token", csrf),
+            ])
+            .to_request()
+    };
+
+    let first = login().await;
+    let res = test::call_service(&app, generate(&first)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_string(res).await;
+    assert!(!body.contains("What changed since last generation"));
+
+    // pretend the room was different when the calendar was generated last time,
+    // which was long enough ago for the DTSTAMPs to differ
+    let owner = store::owner_key(&state.session_key, mock_portal::USERNAME, 1);
+    let mut previous = state.calendars.find_subscription(&owner).unwrap().unwrap();
+    previous.ical = previous
+        .ical
+        .split_inclusive("\r\n")
+        .map(|line| {
+            if line.starts_with("DTSTAMP") {
+                "DTSTAMP:20240801T120000Z\r\n"
+            } else {
+                line
+            }
+        })
+        .collect();
+    for period in &mut previous.generated_courses[0].periods {
+        if period.room == "AB3-302" {
+            period.room = "AB1-201".to_string();
+        }
+    }
+    state.calendars.put_subscription(&previous).unwrap();
+
+    // in a new session, long after the downloadable calendar expired
+    let second = login().await;
+    let res = test::call_service(&app, generate(&second)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_string(res).await;
+    assert!(body.contains("What changed since last generation"));
+    assert!(body.contains("CSE101: Monday 08:30AM–10:00AM moved from AB1-201 to AB3-302"));
+    assert!(body.contains("CSE101: Wednesday 08:30AM–10:00AM moved from AB1-201 to AB3-302"));
+    assert!(!body.contains("MAT101:"));
+    assert!(body.contains(&format!("/subscriptions/{}.ics", previous.token)));
+    // the events themselves didn't change, so calendar apps mustn't see them as modified
+    let regenerated = state.calendars.find_subscription(&owner).unwrap().unwrap();
+    assert!(regenerated
+        .ical
+        .lines()
+        .filter(|line| line.starts_with("DTSTAMP"))
+        .all(|line| line == "DTSTAMP:20240801T120000Z"));
+
+    let res = test::call_service(&app, generate(&second)).await;
+    assert!(body_string(res).await.contains("Nothing changed."));
+}
+
+#[actix_web::test]
+async fn api_login_to_download() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/login")
+        .set_json(serde_json::json!({
+            "username": mock_portal::USERNAME,
+            "password": mock_portal::PASSWORD,
+        }))
+        .to_request();
+    let res: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let auth = format!("Bearer {}", res["token"].as_str().unwrap());
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/semesters")
+        .insert_header((header::AUTHORIZATION, auth.clone()))
+        .to_request();
+    let semesters: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(semesters[0]["id"], 1);
+    assert_eq!(semesters[0]["name"], "Fall-2024");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/semesters/1/courses")
+        .insert_header((header::AUTHORIZATION, auth.clone()))
+        .to_request();
+    let courses: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(courses[0]["course_code"], "CSE101");
+    assert_eq!(
+        courses[0]["periods"][0],
+        serde_json::json!({
+            "day": "Monday",
+            "start_time": { "hours": 8, "minutes": 30 },
+            "end_time": { "hours": 10, "minutes": 0 },
+            "room": "AB3-302",
+            "lab": false,
+        })
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/calendars")
+        .insert_header((header::AUTHORIZATION, auth.clone()))
+        .set_json(serde_json::json!({
+            "semester_id": 1,
+            "name": "Fall 2024",
+            "start_date": "2024-09-01",
+            "end_date": "2024-12-19",
+            "options": { "reminders": { "class": 10 } },
+        }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let created: serde_json::Value = test::read_body_json(res).await;
+
+    let req = test::TestRequest::get()
+        .uri(created["url"].as_str().unwrap())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let ical = body_string(res).await;
+    assert!(ical.contains("SUMMARY:CSE101 (2)"));
+    assert!(ical.contains("TRIGGER:-PT10M"));
+}
+
+async fn error_code(res: ServiceResponse<impl MessageBody>) -> (StatusCode, String) {
+    let status = res.status();
+    let body: serde_json::Value = test::read_body_json(res).await;
+    (status, body["error"]["code"].as_str().unwrap().to_string())
+}
+
+#[actix_web::test]
+async fn api_errors_have_codes() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    for (req, status, code) in [
+        (
+            test::TestRequest::get().uri("/api/v1/semesters"),
+            StatusCode::UNAUTHORIZED,
+            "missing_token",
+        ),
+        (
+            test::TestRequest::post()
+                .uri("/api/v1/login")
+                .set_json(serde_json::json!({ "username": "x", "password": "wrong" })),
+            StatusCode::UNAUTHORIZED,
+            "invalid_credentials",
+        ),
+        (
+            test::TestRequest::post()
+                .uri("/api/v1/login")
+                .set_json(serde_json::json!({ "username": "x" })),
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+        ),
+        (
+            test::TestRequest::get().uri("/api/v1/calendars/unknown"),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+        (
+            test::TestRequest::get().uri("/api/v1/nothing"),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+    ] {
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(error_code(res).await, (status, code.to_string()));
+    }
+}
+
+#[actix_web::test]
+async fn openapi_spec_matches_handlers() {
+    let portal = MockPortal::start().unwrap();
+    let app = test::init_service(app(state(portal.config()))).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/openapi.json")
+        .to_request();
+    let spec: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    for schema in [
+        "Course",
+        "Period",
+        "Time",
+        "Weekday",
+        "Semester",
+        "ErrorBody",
+    ] {
+        assert!(
+            spec["components"]["schemas"][schema].is_object(),
+            "{} is missing",
+            schema
+        );
+    }
+
+    // every handler of the /api/v1 scope is documented and nothing else is
+    let documented = spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, operations)| {
+            operations
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(move |method| (method.clone(), path.clone()))
+        })
+        .collect::<BTreeSet<_>>();
+    let handlers = include_str!("routes/api.rs")
+        .lines()
+        .filter_map(|line| {
+            let (method, path) = line.strip_prefix("#[")?.split_once("(\"")?;
+            let path = path.strip_suffix("\")]")?;
+            ["get", "post", "put", "patch", "delete"]
+                .contains(&method)
+                .then(|| (method.to_string(), path.to_string()))
+        })
+        // the spec itself is served outside the scope
+        .filter(|(_, path)| !path.starts_with("/api/"))
+        .map(|(method, path)| (method, format!("/api/v1{}", path)))
+        .collect::<BTreeSet<_>>();
+    assert_eq!(documented, handlers);
+
+    // every documented operation must reach a handler rather than the fallback
+    let paths = spec["paths"].as_object().unwrap();
+    for (path, operations) in paths {
+        for method in operations.as_object().unwrap().keys() {
+            let uri = path
+                .replace("{semester_id}", "1")
+                .replace("{id}", "unknown");
+            let req = test::TestRequest::default()
+                .method(method.to_uppercase().parse().unwrap())
+                .uri(&uri)
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            if res.status() == StatusCode::NOT_FOUND {
+                let body: serde_json::Value = test::read_body_json(res).await;
+                assert_ne!(
+                    body["error"]["message"],
+                    routes::api::UNKNOWN_ROUTE,
+                    "{} {} has no handler",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+}
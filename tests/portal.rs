@@ -0,0 +1,216 @@
+use ewubd_timetable_calendar_lib::{
+    auth, courses,
+    error::TimetableError,
+    mock_portal::{self, MockData, MockPortal},
+    periods::Time,
+    portal::PortalConfig,
+    semester, utils,
+};
+
+#[tokio::test]
+async fn login_page_has_session_and_captcha() {
+    let portal = MockPortal::start().unwrap();
+    let config = portal.config();
+
+    let res = auth::fetch_login_page(&config).await.unwrap();
+    let session_id = auth::get_session_id(&res).unwrap();
+    assert!(session_id.starts_with("ASP.NET_SessionId=mock"));
+    assert!(!session_id.contains(';'));
+
+    let html = res.text().await.unwrap();
+    let addends = auth::get_captcha_addends(&html).unwrap();
+    assert_eq!(addends, (mock_portal::FIRST_NO, mock_portal::SECOND_NO));
+}
+
+#[tokio::test]
+async fn authenticate_returns_welcome_message() {
+    let portal = MockPortal::start().unwrap();
+    let config = portal.config();
+
+    let res = auth::fetch_login_page(&config).await.unwrap();
+    let session_id = auth::get_session_id(&res).unwrap();
+    let html = res.text().await.unwrap();
+    let (first_num, second_num) = auth::get_captcha_addends(&html).unwrap();
+    assert!(auth::get_hidden_fields(&html).is_empty());
+
+    let client = utils::build_authenticated_client(&config, &session_id).unwrap();
+    let welcome = auth::authenticate(
+        &client,
+        &config,
+        mock_portal::USERNAME,
+        mock_portal::PASSWORD,
+        first_num,
+        second_num,
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(welcome, mock_portal::STUDENT_NAME);
+}
+
+#[tokio::test]
+async fn authenticate_reports_portal_error() {
+    let portal = MockPortal::start().unwrap();
+    let config = portal.config();
+
+    let err = auth::login(&config, mock_portal::USERNAME, "wrong")
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(err, TimetableError::InvalidCredentials(ref msg) if msg == "Invalid username or password")
+    );
+}
+
+#[tokio::test]
+async fn fetches_semesters() {
+    let portal = MockPortal::start().unwrap();
+    let config = portal.config();
+
+    let session_id = auth::login(&config, mock_portal::USERNAME, mock_portal::PASSWORD)
+        .await
+        .unwrap();
+    let client = utils::build_authenticated_client(&config, &session_id).unwrap();
+
+    let semesters = semester::get_all_semesters(&client, &config).await.unwrap();
+
+    assert_eq!(semesters.len(), 2);
+    assert_eq!(semesters[0].id, 1);
+    assert_eq!(semesters[0].name, "Fall-2024");
+    assert_eq!(semesters[0].start_date.to_string(), "2024-09-01");
+    assert_eq!(semesters[0].end_date.to_string(), "2024-12-19");
+}
+
+#[tokio::test]
+async fn fetches_courses_skipping_dropped() {
+    let portal = MockPortal::start().unwrap();
+    let config = portal.config();
+
+    let session_id = auth::login(&config, mock_portal::USERNAME, mock_portal::PASSWORD)
+        .await
+        .unwrap();
+    let client = utils::build_authenticated_client(&config, &session_id).unwrap();
+
+    let courses = courses::get_courses(&client, &config, 1).await.unwrap();
+
+    assert_eq!(courses.len(), 2);
+    assert_eq!(courses[0].course_code, "CSE101");
+    assert_eq!(courses[0].section, 2);
+    assert_eq!(courses[0].lecturer, "Jane Doe");
+    // theory and lab slots are merged into one course
+    assert_eq!(courses[0].periods.len(), 3);
+    assert_eq!(courses[0].periods[2].room, "630");
+    assert_eq!(courses[0].periods[2].start_time, Time::new(10, 10));
+    assert_eq!(courses[1].course_code, "MAT101");
+
+    assert!(courses::get_courses(&client, &config, 2)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn rejects_unauthenticated_session() {
+    let portal = MockPortal::start().unwrap();
+    let config = portal.config();
+
+    let res = auth::fetch_login_page(&config).await.unwrap();
+    let session_id = auth::get_session_id(&res).unwrap();
+    let client = utils::build_authenticated_client(&config, &session_id).unwrap();
+
+    let err = semester::get_all_semesters(&client, &config)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, TimetableError::SessionExpired));
+}
+
+#[tokio::test]
+async fn serves_custom_data() {
+    let portal = MockPortal::start_with(MockData {
+        password: "p@ss".to_string(),
+        ..MockData::default()
+    })
+    .unwrap();
+
+    assert!(auth::login(&portal.config(), mock_portal::USERNAME, "p@ss")
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn login_encodes_special_characters() {
+    // each of these was cut short or split into extra fields when the form
+    // body was built by hand
+    for password in ["p&ss=w+rd", "100%25 sure", "x&Answer=0", "ünïcødé pass"] {
+        let portal = MockPortal::start_with(MockData {
+            password: password.to_string(),
+            ..MockData::default()
+        })
+        .unwrap();
+        let config = portal.config();
+
+        assert!(
+            auth::login(&config, mock_portal::USERNAME, password)
+                .await
+                .is_ok(),
+            "cannot login with {:?}",
+            password
+        );
+
+        let err = auth::login(&config, mock_portal::USERNAME, &format!("{}x", password))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TimetableError::InvalidCredentials(_)));
+    }
+}
+
+#[tokio::test]
+async fn login_posts_anti_forgery_token() {
+    let token = "CfDJ8+a/b=c%d";
+    let portal = MockPortal::start_with(MockData {
+        anti_forgery_token: Some(token.to_string()),
+        ..MockData::default()
+    })
+    .unwrap();
+    let config = portal.config();
+
+    let res = auth::fetch_login_page(&config).await.unwrap();
+    let session_id = auth::get_session_id(&res).unwrap();
+    assert!(session_id.starts_with("ASP.NET_SessionId=mock"));
+    assert!(session_id.ends_with(&format!("; {}={}", mock_portal::ANTI_FORGERY_FIELD, token)));
+    assert_eq!(
+        auth::get_hidden_fields(&res.text().await.unwrap()),
+        vec![(
+            mock_portal::ANTI_FORGERY_FIELD.to_string(),
+            token.to_string()
+        )]
+    );
+
+    let session_id = auth::login(&config, mock_portal::USERNAME, mock_portal::PASSWORD)
+        .await
+        .unwrap();
+    let client = utils::build_authenticated_client(&config, &session_id).unwrap();
+    assert_eq!(
+        semester::get_all_semesters(&client, &config)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn unreachable_portal_is_unavailable() {
+    // nothing listens on a port right after its listener is dropped
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = PortalConfig::new(&format!("http://{}", addr));
+
+    let err = auth::fetch_login_page(&config).await.unwrap_err();
+
+    assert!(matches!(err, TimetableError::PortalUnavailable(_)));
+}